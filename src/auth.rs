@@ -0,0 +1,190 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Authentication
+use std::ascii::AsciiExt;
+use std::fmt;
+
+use iron::{Request, Response};
+use iron::headers::{Authorization, Basic};
+use iron::typemap::Key;
+
+/// An authenticated principal.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Principal {
+    /// The name identifying the principal, e.g. a user name
+    pub name: String,
+    /// The authentication scheme used, e.g. `Basic`
+    pub scheme: String
+}
+
+/// An authentication challenge, sent in a WWW-Authenticate header.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Challenge {
+    /// The authentication scheme, e.g. `Basic`
+    pub scheme: String,
+    /// The protection space
+    pub realm: Option<String>,
+    /// Additional auth-params, e.g. `error` for the Bearer scheme
+    pub params: Vec<(String, String)>
+}
+
+impl Challenge {
+    /// Return a challenge for the Basic scheme, as in RFC 7617.
+    pub fn basic(realm: &str) -> Challenge {
+        Challenge {
+            scheme: "Basic".to_string(),
+            realm: Some(realm.to_string()),
+            params: vec![("charset".to_string(), "UTF-8".to_string())]
+        }
+    }
+
+    /// Return a challenge for the Bearer scheme, as in RFC 6750.
+    pub fn bearer(realm: &str) -> Challenge {
+        Challenge {
+            scheme: "Bearer".to_string(),
+            realm: Some(realm.to_string()),
+            params: vec![]
+        }
+    }
+}
+
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\""))
+}
+
+impl fmt::Display for Challenge {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut params = vec![];
+        if let Some(ref realm) = self.realm {
+            params.push(format!("realm={}", quoted(&realm[])));
+        }
+        for &(ref name, ref value) in self.params.iter() {
+            params.push(format!("{}={}", name, quoted(&value[])));
+        }
+        if params.is_empty() {
+            write!(fmt, "{}", self.scheme)
+        } else {
+            write!(fmt, "{} {}", self.scheme, params.connect(", "))
+        }
+    }
+}
+
+/// The outcome of authenticating a request.
+#[derive(Debug,PartialEq,Clone)]
+pub enum Authentication {
+    /// No authentication was required
+    Anonymous,
+    /// The request was authenticated as the given principal
+    Authenticated(Principal),
+    /// The request was not authenticated, and the client is challenged
+    /// to provide credentials, resulting in a 401 Unauthorized reply
+    Challenge(Vec<Challenge>),
+    /// Authentication could not be performed, resulting in a 500
+    /// InternalServerError reply
+    Error(String)
+}
+
+impl Key for Authentication {
+    type Value = Authentication;
+}
+
+/// Return the outcome of authenticating the request for a response.
+pub fn authentication(resp: &Response) -> Option<&Authentication> {
+    resp.extensions.get::<Authentication>()
+}
+
+/// Return the authenticated principal for a response.
+pub fn principal(resp: &Response) -> Option<&Principal> {
+    match authentication(resp) {
+        Some(&Authentication::Authenticated(ref principal)) => Some(principal),
+        _ => None
+    }
+}
+
+/// Set a WWW-Authenticate header for each challenge.
+pub fn set_challenges(resp: &mut Response, challenges: &[Challenge]) {
+    if !challenges.is_empty() {
+        resp.headers.set_raw(
+            "WWW-Authenticate",
+            challenges.iter().map(|c| c.to_string().into_bytes()).collect());
+    }
+}
+
+/// Return the user name and password from a Basic Authorization
+/// header.
+pub fn basic_credentials(req: &Request) -> Option<(String, Option<String>)> {
+    match req.headers.get::<Authorization<Basic>>() {
+        Some(&Authorization(ref basic)) =>
+            Some((basic.username.clone(), basic.password.clone())),
+        None => None
+    }
+}
+
+/// Return the token from a Bearer Authorization header.
+pub fn bearer_token(req: &Request) -> Option<String> {
+    let value = match req.headers.get_raw("Authorization") {
+        Some(values) if values.len() == 1 =>
+            match String::from_utf8(values[0].clone()) {
+                Ok(value) => value,
+                Err(_) => return None
+            },
+        _ => return None
+    };
+    parse_bearer(&value[])
+}
+
+/// Return the token from a Bearer credentials value.  The value is
+/// split at its first space, so that a client supplied value is never
+/// sliced within a multi-byte character.
+fn parse_bearer(value: &str) -> Option<String> {
+    let value = value.trim();
+    let space = match value.find(' ') {
+        Some(i) => i,
+        None => return None
+    };
+    let token = value[space + 1..].trim();
+    if value[..space].eq_ignore_ascii_case("Bearer") && !token.is_empty() {
+        Some(token.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::parse_bearer;
+
+    #[test]
+    fn test_challenge_display() {
+        assert_eq!("Basic realm=\"api\", charset=\"UTF-8\"",
+                   Challenge::basic("api").to_string());
+        let mut bearer = Challenge::bearer("api");
+        bearer.params.push(("error".to_string(), "invalid_token".to_string()));
+        assert_eq!("Bearer realm=\"api\", error=\"invalid_token\"",
+                   bearer.to_string());
+    }
+
+    #[test]
+    fn test_parse_bearer() {
+        assert_eq!(Some("mF_9.B5f-4.1JqM".to_string()),
+                   parse_bearer("Bearer mF_9.B5f-4.1JqM"));
+        assert_eq!(Some("abc".to_string()), parse_bearer("bearer  abc "));
+        assert_eq!(None, parse_bearer("Basic QWxhZGRpbjpvcGVu"));
+        assert_eq!(None, parse_bearer("Bearer "));
+        assert_eq!(None, parse_bearer("\u{c4}\u{c4}\u{c4}\u{c4}"));
+        assert_eq!(None, parse_bearer("Bear\u{c4}r token"));
+    }
+}
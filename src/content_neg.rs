@@ -13,10 +13,61 @@
 // limitations under the License.
 
 /// Content Negotiation
-use iron::headers::{Encoding,QualityItem};
+use iron::Response;
+use iron::headers::{self,Encoding,Headers,QualityItem};
+use iron::typemap::Key;
 use mime::{Mime,TopLevel,SubLevel};
+use std::ascii::AsciiExt;
 use std::cmp::Ordering::Equal;
 use log;
+use hyper_headers::AcceptCharset;
+
+/// The outcome of content negotiation for a request.  It is kept in
+/// the response extensions, where actions and handlers can read it.
+#[derive(Debug,PartialEq,Clone,Default)]
+pub struct Negotiated {
+    /// The negotiated media type
+    pub content_type: Option<Mime>,
+    /// The negotiated language
+    pub language: Option<String>,
+    /// The negotiated charset
+    pub charset: Option<String>,
+    /// The negotiated content coding
+    pub encoding: Option<Encoding>
+}
+
+impl Key for Negotiated {
+    type Value = Negotiated;
+}
+
+/// Return the outcome of content negotiation for a response.
+pub fn negotiated(resp: &Response) -> Negotiated {
+    match resp.extensions.get::<Negotiated>() {
+        Some(n) => n.clone(),
+        None => Negotiated::default()
+    }
+}
+
+/// Return the outcome of content negotiation for a response, for
+/// update.
+pub fn negotiated_mut(resp: &mut Response) -> &mut Negotiated {
+    if !resp.extensions.contains::<Negotiated>() {
+        resp.extensions.insert::<Negotiated>(Negotiated::default());
+    }
+    resp.extensions.get_mut::<Negotiated>().unwrap()
+}
+
+/// True if every parameter of the requested mime type x is present in
+/// the available mime type y.  Parameter names are case insensitive.
+fn params_match(x: &Mime, y: &Mime) -> bool {
+    x.2.iter().all(
+        |&(ref xa, ref xv)|
+        y.2.iter().any(
+            |&(ref ya, ref yv)|
+            xa.to_string().to_ascii_lowercase() ==
+                ya.to_string().to_ascii_lowercase() &&
+                xv.to_string() == yv.to_string()))
+}
 
 /// Compare a requested mime type x (with wild cards), to an available
 /// mime type y, to see if they match
@@ -24,104 +75,373 @@ fn mime_match(x: &Mime, y: &Mime) -> bool {
     match x {
         &Mime(TopLevel::Star,_,_) => true,
         &Mime(ref tl,SubLevel::Star,_) => tl==&y.0,
-        _ => x==y
+        _ => x.0==y.0 && x.1==y.1 && params_match(x, y)
     }
 }
 
-/// Return the best allowed content type for the request.  The best
-/// type is the first type in the Accept header that is available.
+/// The specificity of a media range, as used to order ranges in RFC
+/// 7231 section 5.3.2.  A range with parameters is more specific than
+/// one without.
+fn specificity(x: &Mime) -> usize {
+    match x {
+        &Mime(TopLevel::Star,_,_) => 0,
+        &Mime(_,SubLevel::Star,_) => 1,
+        _ => 2 + x.2.len()
+    }
+}
+
+/// Return the quality assigned to the available mime type by the most
+/// specific matching media range, or None if no range matches.
+fn content_type_quality(accept: &[QualityItem<Mime>], avail: &Mime) -> Option<f32> {
+    let mut best: Option<&QualityItem<Mime>> = None;
+    for m in accept.iter().filter(|m| mime_match(&m.item, avail)) {
+        if best.map_or(true, |b| specificity(&m.item) > specificity(&b.item)) {
+            best = Some(m);
+        }
+    }
+    best.map(|m| m.quality)
+}
+
+/// Return the best allowed content type for the request.  Each
+/// available type is given the quality of the most specific media
+/// range that matches it, types with a quality of zero are not
+/// acceptable, and the available type with the highest quality is
+/// returned.  Ties are resolved in favour of the earlier available
+/// type.
 pub fn best_content_type(accept: &Vec<QualityItem<Mime>>,
                          avail: &Vec<Mime>) -> Option<Mime> {
+    best_weighted_content_type(
+        accept,
+        &avail.iter()
+            .map(|a| QualityItem{ item: a.clone(), quality: 1.0f32 })
+            .collect())
+}
+
+/// Return the best allowed content type for the request, where each
+/// available type carries a server side quality (qs).  The client's
+/// quality for each available type, as in `best_content_type`, is
+/// multiplied by its server side quality, and the available type with
+/// the highest product is returned.
+pub fn best_weighted_content_type(accept: &Vec<QualityItem<Mime>>,
+                                  avail: &Vec<QualityItem<Mime>>)
+                                  -> Option<Mime> {
     debug!("best_content_type {:?} in {:?}",accept, avail);
-    let mut accept = accept.clone();
-    accept.sort_by(
-        |x,y|
-        x.quality.partial_cmp(&y.quality)
-            .unwrap_or(Equal)
-            .reverse());
-    match accept.iter()
-        .find(|m|
-              avail.iter().find(|a| mime_match(&m.item, &a)).is_some()) {
-            Some(qi) => Some(qi.item.clone()),
-            None => None
+    let mut best: Option<(&Mime, f32)> = None;
+    for a in avail.iter() {
+        match content_type_quality(&accept[], &a.item) {
+            Some(q) if q * a.quality > 0.0 &&
+                best.map_or(true, |(_, bq)| q * a.quality > bq) =>
+                best = Some((&a.item, q * a.quality)),
+            _ => ()
         }
+    }
+    best.map(|(a, _)| a.clone())
 }
 
-/// Compare a requested language type x (with wild cards), to an available
-/// language type y, to see if they match
+/// Compare a requested language range x (with wild cards), to an
+/// available language tag y, to see if they match.  This is the basic
+/// filtering of RFC 4647 section 3.3.1: a range matches a tag that it
+/// equals, or that it is a prefix of, ending at a subtag boundary.
+/// Comparison is case insensitive.
 fn language_match(x: &String, y: &String) -> bool {
-    if x==&"*".to_string() { return true; }
-    x==y
+    if x==&"*".to_string() || y==&"*".to_string() { return true; }
+    language_prefix(x, y)
+}
+
+/// True if x equals y, or is a prefix of y ending at a subtag
+/// boundary, ignoring case.
+fn language_prefix(x: &String, y: &String) -> bool {
+    let x = x.to_ascii_lowercase();
+    let y = y.to_ascii_lowercase();
+    y == x || y.starts_with(&format!("{}-", x)[])
 }
 
-/// Return the best allowed language.
-pub fn best_language(mut accept: Vec<QualityItem<String>>,
+/// Return the quality for an available language.  This is the quality
+/// of the most specific range that matches it by basic filtering.
+/// Failing that, the available language is looked up as in RFC 4647
+/// section 3.4, by truncating subtags from the end of each range, and
+/// is given the highest quality of the ranges that truncate to it.
+fn language_quality(accept: &[QualityItem<String>], avail: &String) -> Option<f32> {
+    let mut best: Option<&QualityItem<String>> = None;
+    for l in accept.iter().filter(|l| language_match(&l.item, avail)) {
+        let specific = |l: &QualityItem<String>|
+            if l.item == "*" { 0 } else { l.item.len() };
+        if best.map_or(true, |b| specific(l) > specific(b)) {
+            best = Some(l);
+        }
+    }
+    if best.is_some() {
+        return best.map(|l| l.quality);
+    }
+    accept.iter()
+        .filter(|l| language_prefix(avail, &l.item))
+        .map(|l| l.quality)
+        .fold(None, |q, lq| match q {
+            Some(q) if q >= lq => Some(q),
+            _ => Some(lq)
+        })
+}
+
+/// Return the best allowed language.  This is the available language
+/// with the highest quality, where a quality of zero is not
+/// acceptable.  Ties are resolved in favour of the earlier available
+/// language.
+pub fn best_language(accept: Vec<QualityItem<String>>,
                      avail: &Vec<String>) -> Option<String> {
-    accept.sort_by(
-        |x,y|
-        x.quality.partial_cmp(&y.quality)
-            .unwrap_or(Equal)
-            .reverse());
-    match accept.iter()
-        .find(|m|
-              avail.iter().find(|a| language_match(&m.item, &a)).is_some()) {
-            Some(qi) => Some(qi.item.clone()),
-            None => None
+    let mut best: Option<(&String, f32)> = None;
+    for a in avail.iter() {
+        match language_quality(&accept[], a) {
+            Some(q) if q > 0.0 && best.map_or(true, |(_, bq)| q > bq) =>
+                best = Some((a, q)),
+            _ => ()
         }
+    }
+    best.map(|(a, _)| a.clone())
+}
+
+/// Return the canonical name of a charset, upper cased, with some
+/// common aliases resolved to their preferred MIME name.
+fn canonical_charset(charset: &str) -> String {
+    let name = charset.trim().to_ascii_uppercase();
+    match &name[] {
+        "UTF8" => "UTF-8",
+        "UTF16" => "UTF-16",
+        "LATIN1" | "LATIN-1" | "L1" | "ISO8859-1" | "ISO_8859-1" |
+        "ISO_8859-1:1987" | "ISO-IR-100" | "CP819" | "IBM819" => "ISO-8859-1",
+        "LATIN2" | "LATIN-2" | "L2" | "ISO8859-2" | "ISO_8859-2" => "ISO-8859-2",
+        "LATIN9" | "LATIN-9" | "ISO8859-15" | "ISO_8859-15" => "ISO-8859-15",
+        "ASCII" | "US_ASCII" | "ISO646-US" | "ANSI_X3.4-1968" | "CP367" =>
+            "US-ASCII",
+        "CP1252" | "WINDOWS1252" => "WINDOWS-1252",
+        "SJIS" | "SHIFT-JIS" => "SHIFT_JIS",
+        _ => &name[]
+    }.to_string()
 }
 
 /// Compare a requested charset type x (with wild cards), to an available
 /// charset type y, to see if they match
 fn charset_match(x: &String, y: &String) -> bool {
     if x==&"*".to_string() { return true; }
-    x==y
+    canonical_charset(&x[]) == canonical_charset(&y[])
+}
+
+/// Return the quality for an available charset.  A charset named in
+/// the request takes its own quality, and any other charset the
+/// quality of the `*` wildcard, as in RFC 7231 section 5.3.3.
+fn charset_quality(accept: &[QualityItem<String>], avail: &String) -> Option<f32> {
+    match accept.iter().find(|c| c.item != "*" && charset_match(&c.item, avail)) {
+        Some(c) => Some(c.quality),
+        None => accept.iter()
+            .find(|c| charset_match(&c.item, avail))
+            .map(|c| c.quality)
+    }
 }
 
-/// Return the best allowed charset.
+/// Return the best allowed charset.  This is the available charset
+/// with the highest quality, where a quality of zero is not
+/// acceptable.  Ties are resolved in favour of the earlier available
+/// charset.
 pub fn best_charset(acceptv: &Vec<QualityItem<String>>,
                     avail: &Vec<String>) -> Option<String> {
-    let mut accept = acceptv.clone();
-    accept.sort_by(
-        |x,y|
-        x.quality.partial_cmp(&y.quality)
-            .unwrap_or(Equal)
-            .reverse());
-    match accept.iter()
-        .find(|m|
-              avail.iter().find(|a| charset_match(&m.item, &a)).is_some()) {
-            Some(qi) => Some(qi.item.clone()),
-            None => None
+    let mut best: Option<(&String, f32)> = None;
+    for a in avail.iter() {
+        match charset_quality(&acceptv[], a) {
+            Some(q) if q > 0.0 && best.map_or(true, |(_, bq)| q > bq) =>
+                best = Some((a, q)),
+            _ => ()
         }
+    }
+    best.map(|(a, _)| a.clone())
+}
+
+/// Return the canonical, lower cased, name of a content coding,
+/// treating `x-gzip` and `x-compress` as equivalent to `gzip` and
+/// `compress`, as in RFC 7230 section 4.2.
+fn canonical_encoding(encoding: &Encoding) -> String {
+    let name = encoding.to_string().to_ascii_lowercase();
+    match &name[] {
+        "x-gzip" => "gzip",
+        "x-compress" => "compress",
+        _ => &name[]
+    }.to_string()
+}
+
+fn is_any_encoding(encoding: &Encoding) -> bool {
+    canonical_encoding(encoding) == "*"
 }
 
 /// Compare a requested encoding type x (with wild cards), to an available
 /// encoding type y, to see if they match
 fn encoding_match(x: &Encoding, y: &Encoding) -> bool {
-    x==y
+    is_any_encoding(x) || canonical_encoding(x) == canonical_encoding(y)
+}
+
+/// The quality given to the identity coding when it is acceptable only
+/// because it is not excluded, so that any coding the request names
+/// is preferred to it.
+const IMPLICIT_IDENTITY_QUALITY: f32 = 0.001;
+
+/// Return the quality for an available content coding, as in RFC 7231
+/// section 5.3.4.  A coding named in the request takes its own
+/// quality, and any other the quality of the `*` wildcard.  The
+/// identity coding is acceptable unless it is excluded, either
+/// explicitly or by `*;q=0`.
+fn encoding_quality(accept: &[QualityItem<Encoding>], avail: &Encoding) -> Option<f32> {
+    match accept.iter().find(|e| !is_any_encoding(&e.item) &&
+                             encoding_match(&e.item, avail)) {
+        Some(e) => return Some(e.quality),
+        None => ()
+    }
+    match accept.iter().find(|e| is_any_encoding(&e.item)) {
+        Some(e) => return Some(e.quality),
+        None => ()
+    }
+    if *avail == Encoding::Identity {
+        Some(IMPLICIT_IDENTITY_QUALITY)
+    } else {
+        None
+    }
 }
 
-/// Return the best allowed encoding.
+/// Return the best allowed encoding.  This is the available coding
+/// with the highest quality, where a quality of zero is not
+/// acceptable.  Ties are resolved in favour of the earlier available
+/// coding.  An empty `accept` allows only the identity coding.
 pub fn best_encoding(accept: &Vec<QualityItem<Encoding>>,
                      avail: &Vec<Encoding>) -> Option<Encoding> {
-    let mut accept = accept.clone();
-    accept.sort_by(
-        |x,y|
-        x.quality.partial_cmp(&y.quality)
-            .unwrap_or(Equal)
-            .reverse());
-    match accept.iter()
-        .find(|m|
-              avail.iter().find(|a| encoding_match(&m.item, &a)).is_some()) {
-            Some(qi) => Some(qi.item.clone()),
-            None => None
+    let mut best: Option<(&Encoding, f32)> = None;
+    for a in avail.iter() {
+        match encoding_quality(&accept[], a) {
+            Some(q) if q > 0.0 && best.map_or(true, |(_, bq)| q > bq) =>
+                best = Some((a, q)),
+            _ => ()
         }
+    }
+    best.map(|(a, _)| a.clone())
+}
+
+
+/// A representation of a resource, described by the values of each
+/// negotiable dimension, and a server side source quality.  A
+/// dimension of None is not negotiated.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Variant {
+    /// The media type of the variant
+    pub content_type: Option<Mime>,
+    /// The language of the variant
+    pub language: Option<String>,
+    /// The charset of the variant
+    pub charset: Option<String>,
+    /// The content coding of the variant
+    pub encoding: Option<Encoding>,
+    /// The source quality of the variant, between 0 and 1
+    pub quality: f32
+}
+
+impl Variant {
+    /// Return a variant with the given media type, a source quality of
+    /// 1, and no other negotiable dimensions.
+    pub fn new(content_type: Mime) -> Variant {
+        Variant {
+            content_type: Some(content_type),
+            language: None,
+            charset: None,
+            encoding: None,
+            quality: 1.0f32
+        }
+    }
+}
+
+/// The outcome of negotiating between variants.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Negotiation {
+    /// The chosen variant
+    pub variant: Variant,
+    /// The other acceptable variants, best first
+    pub alternatives: Vec<Variant>
+}
+
+impl Key for Negotiation {
+    type Value = Negotiation;
+}
+
+/// Return the outcome of variant negotiation for a response, if the
+/// resource declared its variants.
+pub fn negotiation(resp: &Response) -> Option<&Negotiation> {
+    resp.extensions.get::<Negotiation>()
+}
+
+/// Return the quality of a single dimension of a variant.  A
+/// dimension that the request does not constrain, or that the variant
+/// does not specify, has a quality of 1.
+fn dimension_quality<T, U, F>(accept: &Option<Vec<QualityItem<T>>>,
+                              value: &Option<U>, quality: F) -> f32
+    where F: Fn(&[QualityItem<T>], &U) -> Option<f32>
+{
+    match (accept, value) {
+        (&Some(ref accept), &Some(ref value)) =>
+            quality(&accept[], value).unwrap_or(0.0),
+        _ => 1.0
+    }
+}
+
+/// Choose the best variant for a request, considering all dimensions
+/// together.  The quality of each variant is its source quality
+/// multiplied by the quality of each of its dimensions, as computed
+/// by the `best_*` functions.  Returns None if no variant is
+/// acceptable.
+pub fn negotiate(request_headers: &Headers, variants: &[Variant]) -> Option<Negotiation> {
+    let accept = match request_headers.get::<headers::Accept>() {
+        Some(&headers::Accept(ref x)) if !x.is_empty() => Some(x.clone()),
+        _ => None
+    };
+    let accept_language = match request_headers.get::<headers::AcceptLanguage>() {
+        Some(&headers::AcceptLanguage(ref x)) =>
+            Some(x.iter()
+                 .map(|l| QualityItem{ item: l.item.to_string(),
+                                       quality: l.quality })
+                 .collect()),
+        None => None
+    };
+    let accept_charset = match request_headers.get::<AcceptCharset>() {
+        Some(&AcceptCharset(ref x)) => Some(x.clone()),
+        None => None
+    };
+    let accept_encoding = match request_headers.get::<headers::AcceptEncoding>() {
+        Some(&headers::AcceptEncoding(ref x)) => Some(x.clone()),
+        None => None
+    };
+    let mut acceptable = variants.iter()
+        .map(|v| (v, v.quality *
+                  dimension_quality(&accept, &v.content_type,
+                                    content_type_quality) *
+                  dimension_quality(&accept_language, &v.language,
+                                    language_quality) *
+                  dimension_quality(&accept_charset, &v.charset,
+                                    charset_quality) *
+                  dimension_quality(&accept_encoding, &v.encoding,
+                                    encoding_quality)))
+        .filter(|&(_, q)| q > 0.0)
+        .collect::<Vec<(&Variant, f32)>>();
+    acceptable.sort_by(|&(_, x), &(_, y)| y.partial_cmp(&x).unwrap_or(Equal));
+    debug!("negotiate {:?}", acceptable);
+    let mut acceptable = acceptable.into_iter().map(|(v, _)| v.clone());
+    match acceptable.next() {
+        Some(variant) => Some(Negotiation {
+            variant: variant,
+            alternatives: acceptable.collect()
+        }),
+        None => None
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use super::mime_match;
-    use mime::{Mime,TopLevel,SubLevel};
+    use super::{best_charset, best_content_type, best_encoding, best_language,
+                best_weighted_content_type, mime_match, negotiate, Variant};
+    use iron::headers::{Encoding, Headers, QualityItem};
+    use mime::{Attr,Mime,TopLevel,SubLevel,Value};
 
     #[test]
     fn test_mime_match() {
@@ -143,4 +463,135 @@ mod tests {
                               SubLevel::Ext("yaml".to_string()),
                               vec![])));
     }
+
+    fn qi(mime: Mime, quality: f32) -> QualityItem<Mime> {
+        QualityItem{ item: mime, quality: quality }
+    }
+
+    #[test]
+    fn test_best_content_type() {
+        let html = Mime(TopLevel::Text, SubLevel::Html, vec![]);
+        let plain = Mime(TopLevel::Text, SubLevel::Plain, vec![]);
+        let json = Mime(TopLevel::Application, SubLevel::Json, vec![]);
+        let text_star = Mime(TopLevel::Text, SubLevel::Star, vec![]);
+        let star_star = Mime(TopLevel::Star, SubLevel::Star, vec![]);
+
+        let accept = vec![qi(text_star.clone(), 0.5), qi(html.clone(), 0.0)];
+        assert_eq!(None, best_content_type(&accept, &vec![html.clone()]));
+        assert_eq!(Some(plain.clone()),
+                   best_content_type(&accept, &vec![html.clone(), plain.clone()]));
+
+        let accept = vec![qi(star_star.clone(), 0.1), qi(json.clone(), 1.0)];
+        assert_eq!(Some(json.clone()),
+                   best_content_type(&accept, &vec![html.clone(), json.clone()]));
+        assert_eq!(Some(html.clone()),
+                   best_content_type(&accept, &vec![html.clone()]));
+
+        let level1 = Mime(TopLevel::Text, SubLevel::Html,
+                          vec![(Attr::Ext("level".to_string()),
+                                Value::Ext("1".to_string()))]);
+        let accept = vec![qi(level1.clone(), 1.0), qi(html.clone(), 0.0)];
+        assert_eq!(None, best_content_type(&accept, &vec![html.clone()]));
+        assert_eq!(Some(level1.clone()),
+                   best_content_type(&accept, &vec![html.clone(), level1.clone()]));
+    }
+
+    #[test]
+    fn test_best_weighted_content_type() {
+        let json = Mime(TopLevel::Application, SubLevel::Json, vec![]);
+        let xml = Mime(TopLevel::Application, SubLevel::Ext("xml".to_string()),
+                       vec![]);
+        let avail = vec![qi(xml.clone(), 0.5), qi(json.clone(), 1.0)];
+
+        let accept = vec![qi(Mime(TopLevel::Star, SubLevel::Star, vec![]), 1.0)];
+        assert_eq!(Some(json.clone()), best_weighted_content_type(&accept, &avail));
+
+        let accept = vec![qi(xml.clone(), 1.0)];
+        assert_eq!(Some(xml.clone()), best_weighted_content_type(&accept, &avail));
+
+        let accept = vec![qi(xml.clone(), 1.0), qi(json.clone(), 0.4)];
+        assert_eq!(Some(xml.clone()), best_weighted_content_type(&accept, &avail));
+    }
+
+    fn items(ranges: &[(&str, f32)]) -> Vec<QualityItem<String>> {
+        ranges.iter()
+            .map(|&(l, q)| QualityItem{ item: l.to_string(), quality: q })
+            .collect()
+    }
+
+    #[test]
+    fn test_best_language() {
+        let avail = vec!["en".to_string(), "fr-CA".to_string()];
+        assert_eq!(Some("en".to_string()),
+                   best_language(items(&[("en-US", 1.0)]), &avail));
+        assert_eq!(Some("en".to_string()),
+                   best_language(items(&[("EN", 1.0)]), &avail));
+        assert_eq!(Some("fr-CA".to_string()),
+                   best_language(items(&[("fr", 1.0), ("en", 0.5)]), &avail));
+        assert_eq!(Some("fr-CA".to_string()),
+                   best_language(items(&[("*", 0.5), ("en", 0.0)]), &avail));
+        assert_eq!(None, best_language(items(&[("de", 1.0)]), &avail));
+    }
+
+    #[test]
+    fn test_best_charset() {
+        let avail = vec!["UTF-8".to_string(), "ISO-8859-1".to_string()];
+        assert_eq!(Some("UTF-8".to_string()),
+                   best_charset(&items(&[("utf-8", 1.0)]), &avail));
+        assert_eq!(Some("ISO-8859-1".to_string()),
+                   best_charset(&items(&[("latin1", 1.0), ("utf-8", 0.5)]),
+                                &avail));
+        assert_eq!(Some("ISO-8859-1".to_string()),
+                   best_charset(&items(&[("*", 1.0), ("utf-8", 0.0)]), &avail));
+        assert_eq!(None, best_charset(&items(&[("utf-8", 0.0)]), &avail));
+    }
+
+    #[test]
+    fn test_best_encoding() {
+        let qe = |e: Encoding, q: f32| QualityItem{ item: e, quality: q };
+        let any = || Encoding::EncodingExt("*".to_string());
+        let br = || Encoding::EncodingExt("br".to_string());
+        let avail = vec![Encoding::Identity, Encoding::Gzip];
+        assert_eq!(Some(Encoding::Identity),
+                   best_encoding(&vec![qe(br(), 1.0)], &avail));
+        assert_eq!(Some(Encoding::Identity), best_encoding(&vec![], &avail));
+        assert_eq!(Some(Encoding::Gzip),
+                   best_encoding(&vec![qe(any(), 1.0)], &avail));
+        assert_eq!(Some(Encoding::Gzip),
+                   best_encoding(&vec![qe(Encoding::EncodingExt("x-gzip".to_string()),
+                                          0.5)], &avail));
+        assert_eq!(None,
+                   best_encoding(&vec![qe(br(), 1.0),
+                                       qe(Encoding::Identity, 0.0)], &avail));
+        assert_eq!(None,
+                   best_encoding(&vec![qe(any(), 0.0)], &vec![Encoding::Identity]));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let html = Mime(TopLevel::Text, SubLevel::Html, vec![]);
+        let json = Mime(TopLevel::Application, SubLevel::Json, vec![]);
+        let variant = |ct: &Mime, language: &str| Variant {
+            language: Some(language.to_string()),
+            ..Variant::new(ct.clone())
+        };
+        let variants = vec![variant(&json, "en"), variant(&json, "fr"),
+                            variant(&html, "en")];
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept", vec![b"text/html".to_vec()]);
+        headers.set_raw("Accept-Language", vec![b"fr".to_vec()]);
+        assert_eq!(None, negotiate(&headers, &variants[]));
+
+        headers.set_raw("Accept-Language", vec![b"fr, en;q=0.5".to_vec()]);
+        let negotiation = negotiate(&headers, &variants[]).unwrap();
+        assert_eq!(variant(&html, "en"), negotiation.variant);
+        assert!(negotiation.alternatives.is_empty());
+
+        headers.set_raw("Accept", vec![b"text/html, application/json;q=0.9".to_vec()]);
+        let negotiation = negotiate(&headers, &variants[]).unwrap();
+        assert_eq!(variant(&json, "fr"), negotiation.variant);
+        assert_eq!(vec![variant(&html, "en"), variant(&json, "en")],
+                   negotiation.alternatives);
+    }
 }
@@ -0,0 +1,89 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Per-request context
+///
+/// A typed context for a request, that decisions can populate, and
+/// later decisions, handlers and actions can read.  Values are keyed
+/// by types implementing `typemap::Key`, and are kept in the request
+/// extensions, so they are also visible from functions that only
+/// receive a `&Request`, such as `Resource::etag`.
+///
+/// ```ignore
+/// struct Entity;
+/// impl Key for Entity { type Value = Document; }
+///
+/// impl Resource for DocumentResource {
+///     fn exists(&self, req: &mut Request, _: &mut Response) -> bool {
+///         match load_document(req) {
+///             Some(doc) => { req.set_context::<Entity>(doc); true },
+///             None => false
+///         }
+///     }
+///
+///     fn etag(&self, req: &Request, _: &mut Response) -> Option<Etag> {
+///         req.context::<Entity>().map(|doc| doc.etag())
+///     }
+/// }
+/// ```
+use std::any::Any;
+
+use iron::Request;
+use iron::typemap::Key;
+
+/// Access to the per-request context.
+pub trait RequestContext {
+    /// Return the context value for the key K.
+    fn context<K: Key>(&self) -> Option<&K::Value> where K::Value: Any;
+
+    /// Return the context value for the key K, for update.
+    fn context_mut<K: Key>(&mut self) -> Option<&mut K::Value> where K::Value: Any;
+
+    /// Set the context value for the key K, returning any previous
+    /// value.
+    fn set_context<K: Key>(&mut self, value: K::Value) -> Option<K::Value>
+        where K::Value: Any;
+
+    /// Return the context value for the key K, computing it with `f`
+    /// if it is not yet set.  The value is computed at most once per
+    /// request.
+    fn context_or_insert_with<K: Key, F>(&mut self, f: F) -> &K::Value
+        where K::Value: Any, F: FnOnce(&mut Request) -> K::Value;
+}
+
+impl RequestContext for Request {
+    fn context<K: Key>(&self) -> Option<&K::Value> where K::Value: Any {
+        self.extensions.get::<K>()
+    }
+
+    fn context_mut<K: Key>(&mut self) -> Option<&mut K::Value> where K::Value: Any {
+        self.extensions.get_mut::<K>()
+    }
+
+    fn set_context<K: Key>(&mut self, value: K::Value) -> Option<K::Value>
+        where K::Value: Any
+    {
+        self.extensions.insert::<K>(value)
+    }
+
+    fn context_or_insert_with<K: Key, F>(&mut self, f: F) -> &K::Value
+        where K::Value: Any, F: FnOnce(&mut Request) -> K::Value
+    {
+        if !self.extensions.contains::<K>() {
+            let value = f(self);
+            self.extensions.insert::<K>(value);
+        }
+        self.extensions.get::<K>().unwrap()
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Cross-Origin Resource Sharing
+use std::ascii::AsciiExt;
+
+use iron::{Request, Response};
+use iron::method::Method;
+
+/// Request headers that are always allowed in cross-origin requests.
+static SIMPLE_HEADERS: &'static [&'static str] =
+    &["accept", "accept-language", "content-language", "content-type"];
+
+/// The CORS policy of a resource.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Cors {
+    /// The allowed origins, e.g. `https://example.com`.  An origin of
+    /// `*` allows any origin.
+    pub origins: Vec<String>,
+    /// The methods allowed in cross-origin requests.  When None, the
+    /// resource's allowed methods are used.
+    pub methods: Option<Vec<Method>>,
+    /// The request headers allowed in cross-origin requests, in
+    /// addition to the simple headers
+    pub headers: Vec<String>,
+    /// The response headers exposed to the client
+    pub expose_headers: Vec<String>,
+    /// Whether requests may include credentials.  Ignored when the
+    /// policy allows any origin, as the CORS specification forbids
+    /// credentials with a wildcard origin; list the trusted origins
+    /// instead.
+    pub credentials: bool,
+    /// The number of seconds for which a preflight reply may be cached
+    pub max_age: Option<u32>
+}
+
+impl Cors {
+    /// Return a policy that allows requests from any origin, without
+    /// credentials, for the resource's allowed methods.
+    pub fn any_origin() -> Cors {
+        Cors {
+            origins: vec!["*".to_string()],
+            methods: None,
+            headers: vec![],
+            expose_headers: vec![],
+            credentials: false,
+            max_age: None
+        }
+    }
+
+    /// True if the policy allows any origin.
+    pub fn allows_any_origin(&self) -> bool {
+        self.origins.iter().any(|o| o == "*")
+    }
+
+    /// True if the policy allows requests from the given origin.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allows_any_origin() ||
+            self.origins.iter().any(|o| o.eq_ignore_ascii_case(origin))
+    }
+
+    /// True if the policy allows the given request header.
+    pub fn allows_header(&self, header: &str) -> bool {
+        SIMPLE_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(header)) ||
+            self.headers.iter().any(|h| h.eq_ignore_ascii_case(header))
+    }
+
+    /// True if the policy allows requests to include credentials.
+    /// This is never the case for a policy that allows any origin.
+    pub fn allows_credentials(&self) -> bool {
+        self.credentials && !self.allows_any_origin()
+    }
+
+    /// True if the Access-Control-Allow-Origin header depends on the
+    /// request's Origin, so that responses must vary on it.
+    pub fn varies_on_origin(&self) -> bool {
+        !self.allows_any_origin()
+    }
+
+    /// Set the Access-Control-Allow-Origin, Access-Control-Allow-Credentials
+    /// and Access-Control-Expose-Headers headers for an allowed origin.
+    pub fn set_headers(&self, resp: &mut Response, origin: &str) {
+        let allow_origin = if self.varies_on_origin() { origin } else { "*" };
+        set_header(resp, "Access-Control-Allow-Origin", allow_origin.to_string());
+        if self.credentials && self.allows_any_origin() {
+            warn!("CORS credentials ignored for a policy allowing any origin");
+        }
+        if self.allows_credentials() {
+            set_header(resp, "Access-Control-Allow-Credentials",
+                       "true".to_string());
+        }
+        if !self.expose_headers.is_empty() {
+            set_header(resp, "Access-Control-Expose-Headers",
+                       self.expose_headers.connect(", "));
+        }
+    }
+
+    /// Set the headers of a successful preflight reply, for the given
+    /// allowed methods.
+    pub fn set_preflight_headers(&self, resp: &mut Response, origin: &str,
+                                 methods: &[Method]) {
+        self.set_headers(resp, origin);
+        set_header(resp, "Access-Control-Allow-Methods",
+                   methods.iter()
+                   .map(|m| m.to_string())
+                   .collect::<Vec<String>>()
+                   .connect(", "));
+        if !self.headers.is_empty() {
+            set_header(resp, "Access-Control-Allow-Headers",
+                       self.headers.connect(", "));
+        }
+        if let Some(max_age) = self.max_age {
+            set_header(resp, "Access-Control-Max-Age", max_age.to_string());
+        }
+    }
+}
+
+fn set_header(resp: &mut Response, name: &'static str, value: String) {
+    resp.headers.set_raw(name, vec![value.into_bytes()]);
+}
+
+fn raw_header(req: &Request, name: &str) -> Option<String> {
+    match req.headers.get_raw(name) {
+        Some(values) if values.len() == 1 =>
+            String::from_utf8(values[0].clone()).ok()
+            .map(|s| s.trim().to_string()),
+        _ => None
+    }
+}
+
+/// Return the request's Origin header.
+pub fn origin(req: &Request) -> Option<String> {
+    raw_header(req, "Origin")
+}
+
+/// Return the method in the request's Access-Control-Request-Method
+/// header.
+pub fn request_method(req: &Request) -> Option<Method> {
+    raw_header(req, "Access-Control-Request-Method")
+        .and_then(|m| m.parse::<Method>().ok())
+}
+
+/// Return the header names in the request's
+/// Access-Control-Request-Headers header.
+pub fn request_headers(req: &Request) -> Vec<String> {
+    match raw_header(req, "Access-Control-Request-Headers") {
+        Some(headers) => headers.split(',')
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .collect(),
+        None => vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iron::Response;
+
+    #[test]
+    fn test_allows() {
+        let cors = Cors {
+            origins: vec!["https://example.com".to_string()],
+            headers: vec!["X-Requested-With".to_string()],
+            ..Cors::any_origin()
+        };
+        assert!(cors.allows_origin("https://example.com"));
+        assert!(!cors.allows_origin("https://example.org"));
+        assert!(cors.allows_header("content-type"));
+        assert!(cors.allows_header("x-requested-with"));
+        assert!(!cors.allows_header("Authorization"));
+        assert!(cors.varies_on_origin());
+        assert!(!Cors::any_origin().varies_on_origin());
+    }
+
+    #[test]
+    fn test_no_credentials_for_any_origin() {
+        let cors = Cors { credentials: true, ..Cors::any_origin() };
+        assert!(!cors.allows_credentials());
+        assert!(!cors.varies_on_origin());
+        let mut resp = Response::new();
+        cors.set_headers(&mut resp, "https://evil.example");
+        assert_eq!(Some(&[b"*".to_vec()][]),
+                   resp.headers.get_raw("Access-Control-Allow-Origin"));
+        assert!(resp.headers.get_raw("Access-Control-Allow-Credentials").is_none());
+    }
+}
@@ -0,0 +1,413 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// The decision graph
+///
+/// A description of the decision graph implemented by the `Resource`
+/// trait, which can be rendered as Graphviz DOT or as JSON.  Node
+/// names are the names recorded in a decision trace, so the path
+/// taken by a traced request can be highlighted.
+use trace::TraceEntry;
+
+/// A decision node in the graph.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub struct Decision {
+    /// The name of the decision
+    pub name: &'static str,
+    /// The node followed when the decision is true
+    pub then: &'static str,
+    /// The node followed when the decision is false
+    pub otherwise: &'static str
+}
+
+/// A terminal node in the graph, either a handler or an action.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub struct Handler {
+    /// The name of the handler
+    pub name: &'static str,
+    /// The status code of the response, or None for actions, where
+    /// the status is set by the resource
+    pub status: Option<u16>
+}
+
+/// An action that, on success, continues to another node of the
+/// graph.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub struct Action {
+    /// The name of the action
+    pub name: &'static str,
+    /// The node followed after the action succeeds
+    pub next: &'static str
+}
+
+/// The decision nodes, starting from the initial decision.
+pub static DECISIONS: &'static [Decision] = &[
+    Decision { name: "service_available",
+               then: "known_method",
+               otherwise: "handle_service_unavailable" },
+    Decision { name: "known_method",
+               then: "uri_too_long",
+               otherwise: "handle_unknown_method" },
+    Decision { name: "uri_too_long",
+               then: "handle_uri_too_long",
+               otherwise: "is_preflight" },
+    Decision { name: "is_preflight",
+               then: "handle_preflight",
+               otherwise: "method_allowed" },
+    Decision { name: "method_allowed",
+               then: "malformed",
+               otherwise: "handle_method_not_allowed" },
+    Decision { name: "malformed",
+               then: "handle_malformed",
+               otherwise: "authorized" },
+    Decision { name: "authorized",
+               then: "allowed",
+               otherwise: "authentication_error" },
+    Decision { name: "authentication_error",
+               then: "handle_authentication_error",
+               otherwise: "handle_unauthorized" },
+    Decision { name: "allowed",
+               then: "valid_content_header",
+               otherwise: "handle_forbidden" },
+    Decision { name: "valid_content_header",
+               then: "known_content_type",
+               otherwise: "handle_not_implemented" },
+    Decision { name: "known_content_type",
+               then: "valid_entity_length",
+               otherwise: "handle_unsupported_media_type" },
+    Decision { name: "valid_entity_length",
+               then: "is_options",
+               otherwise: "handle_payload_too_large" },
+    Decision { name: "is_options",
+               then: "handle_options",
+               otherwise: "has_variants" },
+    Decision { name: "has_variants",
+               then: "variant_available",
+               otherwise: "accept_exists" },
+    Decision { name: "variant_available",
+               then: "processable",
+               otherwise: "handle_not_acceptable" },
+    Decision { name: "accept_exists",
+               then: "media_type_available",
+               otherwise: "accept_language_exists" },
+    Decision { name: "media_type_available",
+               then: "accept_language_exists",
+               otherwise: "handle_not_acceptable" },
+    Decision { name: "accept_language_exists",
+               then: "language_available",
+               otherwise: "accept_charset_exists" },
+    Decision { name: "language_available",
+               then: "accept_charset_exists",
+               otherwise: "handle_not_acceptable" },
+    Decision { name: "accept_charset_exists",
+               then: "charset_available",
+               otherwise: "accept_encoding_exists" },
+    Decision { name: "charset_available",
+               then: "accept_encoding_exists",
+               otherwise: "handle_not_acceptable" },
+    Decision { name: "accept_encoding_exists",
+               then: "encoding_available",
+               otherwise: "processable" },
+    Decision { name: "encoding_available",
+               then: "processable",
+               otherwise: "handle_not_acceptable" },
+    Decision { name: "processable",
+               then: "exists",
+               otherwise: "handle_unprocessable_entity" },
+    Decision { name: "exists",
+               then: "if_match_exists",
+               otherwise: "if_match_star_exists_for_missing" },
+    Decision { name: "if_match_exists",
+               then: "if_match_star",
+               otherwise: "if_unmodified_since_exists" },
+    Decision { name: "if_match_star",
+               then: "if_unmodified_since_exists",
+               otherwise: "if_match" },
+    Decision { name: "if_match",
+               then: "if_unmodified_since_exists",
+               otherwise: "handle_precondition_failed" },
+    Decision { name: "if_unmodified_since_exists",
+               then: "unmodified_since",
+               otherwise: "if_none_match_exists" },
+    Decision { name: "unmodified_since",
+               then: "handle_precondition_failed",
+               otherwise: "if_none_match_exists" },
+    Decision { name: "if_none_match_exists",
+               then: "if_none_match_star",
+               otherwise: "if_modified_since_exists" },
+    Decision { name: "if_none_match_star",
+               then: "none_match_status",
+               otherwise: "if_none_match" },
+    Decision { name: "if_none_match",
+               then: "none_match_status",
+               otherwise: "if_modified_since_exists" },
+    Decision { name: "none_match_status",
+               then: "handle_not_modified",
+               otherwise: "handle_precondition_failed" },
+    Decision { name: "if_modified_since_exists",
+               then: "modified_since",
+               otherwise: "method_delete" },
+    Decision { name: "modified_since",
+               then: "method_delete",
+               otherwise: "handle_not_modified" },
+    Decision { name: "method_delete",
+               then: "delete_action",
+               otherwise: "method_patch" },
+    Decision { name: "method_patch",
+               then: "known_patch_type",
+               otherwise: "post_to_existing" },
+    Decision { name: "known_patch_type",
+               then: "patch_conflict",
+               otherwise: "handle_unsupported_patch_type" },
+    Decision { name: "patch_conflict",
+               then: "handle_conflict",
+               otherwise: "patch_action" },
+    Decision { name: "post_to_existing",
+               then: "post_action",
+               otherwise: "put_to_existing" },
+    Decision { name: "put_to_existing",
+               then: "conflict",
+               otherwise: "multiple_representations" },
+    Decision { name: "if_match_star_exists_for_missing",
+               then: "handle_precondition_failed",
+               otherwise: "method_put" },
+    Decision { name: "method_put",
+               then: "put_to_different_url",
+               otherwise: "existed" },
+    Decision { name: "put_to_different_url",
+               then: "handle_moved_permanently",
+               otherwise: "can_put_to_missing" },
+    Decision { name: "can_put_to_missing",
+               then: "conflict",
+               otherwise: "handle_not_implemented" },
+    Decision { name: "conflict",
+               then: "handle_conflict",
+               otherwise: "put_action" },
+    Decision { name: "existed",
+               then: "moved_permanently",
+               otherwise: "post_to_missing" },
+    Decision { name: "moved_permanently",
+               then: "handle_moved_permanently",
+               otherwise: "moved_temporarily" },
+    Decision { name: "moved_temporarily",
+               then: "handle_moved_temporarily",
+               otherwise: "post_to_gone" },
+    Decision { name: "post_to_gone",
+               then: "can_post_to_gone",
+               otherwise: "handle_gone" },
+    Decision { name: "can_post_to_gone",
+               then: "post_action",
+               otherwise: "handle_gone" },
+    Decision { name: "post_to_missing",
+               then: "can_post_to_missing",
+               otherwise: "handle_not_found" },
+    Decision { name: "can_post_to_missing",
+               then: "post_action",
+               otherwise: "handle_not_found" },
+    Decision { name: "delete_enacted",
+               then: "respond_with_entity",
+               otherwise: "handle_accepted" },
+    Decision { name: "post_redirect",
+               then: "handle_see_other",
+               otherwise: "new" },
+    Decision { name: "new",
+               then: "handle_created",
+               otherwise: "respond_with_entity" },
+    Decision { name: "respond_with_entity",
+               then: "multiple_representations",
+               otherwise: "handle_no_content" },
+    Decision { name: "multiple_representations",
+               then: "handle_multiple_representations",
+               otherwise: "method_get" },
+    Decision { name: "method_get",
+               then: "get",
+               otherwise: "handle_ok" },
+];
+
+/// The terminal nodes.
+pub static HANDLERS: &'static [Handler] = &[
+    Handler { name: "handle_service_unavailable", status: Some(503) },
+    Handler { name: "handle_unknown_method", status: Some(501) },
+    Handler { name: "handle_uri_too_long", status: Some(501) },
+    Handler { name: "handle_preflight", status: Some(200) },
+    Handler { name: "handle_method_not_allowed", status: Some(405) },
+    Handler { name: "handle_malformed", status: Some(400) },
+    Handler { name: "handle_unauthorized", status: Some(401) },
+    Handler { name: "handle_authentication_error", status: Some(500) },
+    Handler { name: "handle_forbidden", status: Some(403) },
+    Handler { name: "handle_not_implemented", status: Some(501) },
+    Handler { name: "handle_unsupported_media_type", status: Some(415) },
+    Handler { name: "handle_unsupported_patch_type", status: Some(415) },
+    Handler { name: "handle_payload_too_large", status: Some(413) },
+    Handler { name: "handle_options", status: Some(200) },
+    Handler { name: "handle_not_acceptable", status: Some(406) },
+    Handler { name: "handle_unprocessable_entity", status: Some(422) },
+    Handler { name: "handle_precondition_failed", status: Some(412) },
+    Handler { name: "handle_not_modified", status: Some(304) },
+    Handler { name: "handle_moved_permanently", status: Some(308) },
+    Handler { name: "handle_moved_temporarily", status: Some(307) },
+    Handler { name: "handle_conflict", status: Some(409) },
+    Handler { name: "handle_gone", status: Some(410) },
+    Handler { name: "handle_not_found", status: Some(404) },
+    Handler { name: "handle_see_other", status: Some(303) },
+    Handler { name: "handle_ok", status: Some(200) },
+    Handler { name: "handle_created", status: Some(201) },
+    Handler { name: "handle_accepted", status: Some(202) },
+    Handler { name: "handle_no_content", status: Some(204) },
+    Handler { name: "handle_multiple_representations", status: Some(300) },
+    Handler { name: "get", status: None },
+];
+
+/// The actions that continue with further decisions.
+pub static ACTIONS: &'static [Action] = &[
+    Action { name: "post_action", next: "post_redirect" },
+    Action { name: "put_action", next: "new" },
+    Action { name: "delete_action", next: "delete_enacted" },
+    Action { name: "patch_action", next: "respond_with_entity" },
+];
+
+/// Return the decision node with the given name.
+pub fn decision(name: &str) -> Option<&'static Decision> {
+    DECISIONS.iter().find(|d| d.name == name)
+}
+
+/// Return the terminal node with the given name.
+pub fn handler(name: &str) -> Option<&'static Handler> {
+    HANDLERS.iter().find(|h| h.name == name)
+}
+
+/// Return the action node with the given name.
+pub fn action(name: &str) -> Option<&'static Action> {
+    ACTIONS.iter().find(|a| a.name == name)
+}
+
+fn visited(path: &[TraceEntry], name: &str) -> bool {
+    path.iter().any(|e| match *e {
+        TraceEntry::Decision(n, _) => n == name,
+        TraceEntry::Handler(n) => n == name
+    })
+}
+
+fn taken(path: &[TraceEntry], name: &str, value: bool) -> bool {
+    path.iter().any(|e| *e == TraceEntry::Decision(name, value))
+}
+
+fn dot_edge(out: &mut String, from: &str, to: &str, value: bool,
+            highlight: bool) {
+    out.push_str(&format!("  \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+                          from, to, value,
+                          if highlight { ", color=red, penwidth=2" }
+                          else { "" })[]);
+}
+
+/// Render the decision graph in Graphviz DOT format.  The nodes and
+/// edges of `path`, a decision trace, are highlighted.
+pub fn to_dot(path: &[TraceEntry]) -> String {
+    let mut out = "digraph austenite {\n".to_string();
+    for d in DECISIONS.iter() {
+        out.push_str(&format!("  \"{}\" [shape=diamond{}];\n",
+                              d.name,
+                              if visited(path, d.name) { ", color=red" }
+                              else { "" })[]);
+    }
+    for h in HANDLERS.iter() {
+        let label = match h.status {
+            Some(status) => format!("{}\\n{}", h.name, status),
+            None => h.name.to_string()
+        };
+        out.push_str(&format!("  \"{}\" [shape=box, label=\"{}\"{}];\n",
+                              h.name, label,
+                              if visited(path, h.name) { ", color=red" }
+                              else { "" })[]);
+    }
+    for a in ACTIONS.iter() {
+        out.push_str(&format!("  \"{}\" [shape=box, style=rounded{}];\n",
+                              a.name,
+                              if visited(path, a.name) { ", color=red" }
+                              else { "" })[]);
+    }
+    for d in DECISIONS.iter() {
+        dot_edge(&mut out, d.name, d.then, true, taken(path, d.name, true));
+        dot_edge(&mut out, d.name, d.otherwise, false,
+                 taken(path, d.name, false));
+    }
+    for a in ACTIONS.iter() {
+        let highlight = visited(path, a.name) && visited(path, a.next);
+        out.push_str(&format!("  \"{}\" -> \"{}\"{};\n",
+                              a.name, a.next,
+                              if highlight { " [color=red, penwidth=2]" }
+                              else { "" })[]);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Render the decision graph as JSON.
+///
+/// The result is an object with a `decisions` array, of objects with
+/// `name`, `then` and `else` keys, a `handlers` array, of objects
+/// with `name` and `status` keys, and an `actions` array, of objects
+/// with `name` and `next` keys.
+pub fn to_json() -> String {
+    let decisions = DECISIONS.iter()
+        .map(|d| format!("{{\"name\":\"{}\",\"then\":\"{}\",\"else\":\"{}\"}}",
+                         d.name, d.then, d.otherwise))
+        .collect::<Vec<String>>();
+    let handlers = HANDLERS.iter()
+        .map(|h| format!("{{\"name\":\"{}\",\"status\":{}}}",
+                         h.name,
+                         match h.status {
+                             Some(status) => status.to_string(),
+                             None => "null".to_string()
+                         }))
+        .collect::<Vec<String>>();
+    let actions = ACTIONS.iter()
+        .map(|a| format!("{{\"name\":\"{}\",\"next\":\"{}\"}}",
+                         a.name, a.next))
+        .collect::<Vec<String>>();
+    format!("{{\"decisions\":[{}],\"handlers\":[{}],\"actions\":[{}]}}",
+            decisions.connect(","), handlers.connect(","), actions.connect(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trace::TraceEntry;
+
+    #[test]
+    fn test_graph_is_closed() {
+        for d in DECISIONS.iter() {
+            for next in [d.then, d.otherwise].iter() {
+                assert!(decision(next).is_some() || handler(next).is_some()
+                        || action(next).is_some(),
+                        "{} leads to unknown node {}", d.name, next);
+            }
+        }
+        for a in ACTIONS.iter() {
+            assert!(decision(a.next).is_some() || handler(a.next).is_some(),
+                    "{} leads to unknown node {}", a.name, a.next);
+        }
+    }
+
+    #[test]
+    fn test_to_dot_highlights_path() {
+        let dot = to_dot(&[TraceEntry::Decision("service_available", false),
+                           TraceEntry::Handler("handle_service_unavailable")]);
+        assert!(dot.contains(
+            "\"service_available\" -> \"handle_service_unavailable\" \
+             [label=\"false\", color=red, penwidth=2];"));
+        assert!(dot.contains(
+            "\"service_available\" -> \"known_method\" [label=\"true\"];"));
+    }
+}
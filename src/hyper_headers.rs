@@ -14,8 +14,13 @@
 
 use hyper::header::{self, EntityTag, QualityItem};
 use hyper::header::parsing::{self};
+use mime::Mime;
 use std::fmt;
 use std::str::FromStr;
+use time::{self, Tm};
+
+/// The IMF-fixdate format of HTTP dates.
+const HTTP_DATE: &'static str = "%a, %d %b %Y %T %Z";
 
 
 /// A charset code
@@ -69,3 +74,316 @@ impl ::std::fmt::Display for AcceptCharset {
         self.fmt_header(f)
     }
 }
+
+fn one_raw_str(raw: &[Vec<u8>]) -> Option<&str> {
+    if raw.len() != 1 {
+        return None;
+    }
+    ::std::str::from_utf8(&raw[0][]).ok().map(|s| s.trim())
+}
+
+/// A byte range specifier in a `Range` header.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ByteRangeSpec {
+    /// The bytes from the first position to the last position,
+    /// inclusive
+    FromTo(u64, u64),
+    /// The bytes from the first position to the end
+    AllFrom(u64),
+    /// The given number of bytes at the end
+    Last(u64)
+}
+
+impl ByteRangeSpec {
+    /// Resolve the range against a representation of `len` bytes,
+    /// returning the inclusive first and last byte positions, or None
+    /// if the range is not satisfiable.
+    pub fn satisfiable_range(&self, len: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRangeSpec::FromTo(first, last) if first < len =>
+                Some((first, ::std::cmp::min(last, len - 1))),
+            ByteRangeSpec::AllFrom(first) if first < len =>
+                Some((first, len - 1)),
+            ByteRangeSpec::Last(n) if n > 0 && len > 0 =>
+                Some((len - ::std::cmp::min(n, len), len - 1)),
+            _ => None
+        }
+    }
+}
+
+impl FromStr for ByteRangeSpec {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ByteRangeSpec, ()> {
+        let s = s.trim();
+        if s.starts_with("-") {
+            return s[1..].parse::<u64>().map(ByteRangeSpec::Last)
+                .map_err(|_| ());
+        }
+        let dash = match s.find('-') {
+            Some(i) => i,
+            None => return Err(())
+        };
+        let first = try!(s[..dash].parse::<u64>().map_err(|_| ()));
+        if dash + 1 == s.len() {
+            return Ok(ByteRangeSpec::AllFrom(first));
+        }
+        let last = try!(s[dash+1..].parse::<u64>().map_err(|_| ()));
+        if last < first {
+            return Err(());
+        }
+        Ok(ByteRangeSpec::FromTo(first, last))
+    }
+}
+
+impl fmt::Display for ByteRangeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ByteRangeSpec::FromTo(first, last) => write!(f, "{}-{}", first, last),
+            ByteRangeSpec::AllFrom(first) => write!(f, "{}-", first),
+            ByteRangeSpec::Last(n) => write!(f, "-{}", n)
+        }
+    }
+}
+
+/// The `Content-Language` header
+///
+/// The `Content-Language` header describes the natural languages of
+/// the intended audience of a representation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ContentLanguage(pub Vec<String>);
+
+impl header::Header for ContentLanguage {
+    fn header_name() -> &'static str {
+        "Content-Language"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<ContentLanguage> {
+        header::parsing::from_comma_delimited(raw).map(ContentLanguage)
+    }
+}
+
+impl header::HeaderFormat for ContentLanguage {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        header::parsing::fmt_comma_delimited(fmt, &self.0[])
+    }
+}
+
+/// The `Accept-Patch` header
+///
+/// The `Accept-Patch` header lists the media types of patch documents
+/// accepted by a resource, as specified in RFC 5789.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AcceptPatch(pub Vec<Mime>);
+
+impl header::Header for AcceptPatch {
+    fn header_name() -> &'static str {
+        "Accept-Patch"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<AcceptPatch> {
+        header::parsing::from_comma_delimited(raw).map(AcceptPatch)
+    }
+}
+
+impl header::HeaderFormat for AcceptPatch {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        header::parsing::fmt_comma_delimited(fmt, &self.0[])
+    }
+}
+
+/// The `Accept-Post` header
+///
+/// The `Accept-Post` header lists the media types accepted in POST
+/// requests to a resource.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AcceptPost(pub Vec<Mime>);
+
+impl header::Header for AcceptPost {
+    fn header_name() -> &'static str {
+        "Accept-Post"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<AcceptPost> {
+        header::parsing::from_comma_delimited(raw).map(AcceptPost)
+    }
+}
+
+impl header::HeaderFormat for AcceptPost {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        header::parsing::fmt_comma_delimited(fmt, &self.0[])
+    }
+}
+
+/// The `Range` header
+///
+/// The `Range` header requests the transfer of parts of a
+/// representation.  Only the `bytes` range unit is supported.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Range(pub Vec<ByteRangeSpec>);
+
+impl header::Header for Range {
+    fn header_name() -> &'static str {
+        "Range"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<Range> {
+        let s = match one_raw_str(raw) {
+            Some(s) if s.starts_with("bytes=") => &s[6..],
+            _ => return None
+        };
+        let specs = s.split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(|spec| spec.parse::<ByteRangeSpec>())
+            .collect::<Result<Vec<ByteRangeSpec>, ()>>();
+        match specs {
+            Ok(ref specs) if specs.is_empty() => None,
+            Ok(specs) => Some(Range(specs)),
+            Err(_) => None
+        }
+    }
+}
+
+impl header::HeaderFormat for Range {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        try!(write!(fmt, "bytes="));
+        header::parsing::fmt_comma_delimited(fmt, &self.0[])
+    }
+}
+
+/// The `Content-Range` header
+///
+/// The `Content-Range` header gives the position of a partial body
+/// within the complete representation, in bytes.  A `range` of None
+/// is used in 416 RangeNotSatisfiable responses.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ContentRange {
+    /// The inclusive first and last byte positions
+    pub range: Option<(u64, u64)>,
+    /// The length of the complete representation, if known
+    pub length: Option<u64>
+}
+
+impl header::Header for ContentRange {
+    fn header_name() -> &'static str {
+        "Content-Range"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<ContentRange> {
+        let s = match one_raw_str(raw) {
+            Some(s) if s.starts_with("bytes ") => &s[6..],
+            _ => return None
+        };
+        let slash = match s.find('/') {
+            Some(i) => i,
+            None => return None
+        };
+        let length = match &s[slash+1..] {
+            "*" => None,
+            n => match n.parse::<u64>() {
+                Ok(n) => Some(n),
+                Err(_) => return None
+            }
+        };
+        let range = match &s[..slash] {
+            "*" => None,
+            r => match r.parse::<ByteRangeSpec>() {
+                Ok(ByteRangeSpec::FromTo(first, last)) => Some((first, last)),
+                _ => return None
+            }
+        };
+        Some(ContentRange { range: range, length: length })
+    }
+}
+
+impl header::HeaderFormat for ContentRange {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        try!(match self.range {
+            Some((first, last)) => write!(fmt, "bytes {}-{}/", first, last),
+            None => write!(fmt, "bytes */")
+        });
+        match self.length {
+            Some(length) => write!(fmt, "{}", length),
+            None => write!(fmt, "*")
+        }
+    }
+}
+
+/// The `Accept-Ranges` header
+///
+/// The `Accept-Ranges` header indicates the range units supported by
+/// a resource, or `none`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AcceptRanges(pub Vec<String>);
+
+impl header::Header for AcceptRanges {
+    fn header_name() -> &'static str {
+        "Accept-Ranges"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<AcceptRanges> {
+        header::parsing::from_comma_delimited(raw).map(AcceptRanges)
+    }
+}
+
+impl header::HeaderFormat for AcceptRanges {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        header::parsing::fmt_comma_delimited(fmt, &self.0[])
+    }
+}
+
+/// The `If-Range` header
+///
+/// The `If-Range` header makes a `Range` request conditional on the
+/// representation being unchanged, as identified by either an entity
+/// tag or a last modified date.
+#[derive(Clone, PartialEq, Debug)]
+pub enum IfRange {
+    /// An entity tag, compared using strong comparison
+    EntityTag(EntityTag),
+    /// A last modified date, which must match exactly
+    Date(Tm)
+}
+
+impl header::Header for IfRange {
+    fn header_name() -> &'static str {
+        "If-Range"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> Option<IfRange> {
+        match one_raw_str(raw) {
+            Some(s) if s.starts_with("\"") || s.starts_with("W/") =>
+                s.parse::<EntityTag>().ok().map(IfRange::EntityTag),
+            Some(s) => time::strptime(s, HTTP_DATE).ok().map(IfRange::Date),
+            None => None
+        }
+    }
+}
+
+impl header::HeaderFormat for IfRange {
+    fn fmt_header(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            IfRange::EntityTag(ref tag) => write!(fmt, "{}", tag),
+            IfRange::Date(ref date) => write!(fmt, "{}", date.rfc822())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::Header;
+
+    #[test]
+    fn test_range() {
+        assert_eq!(Some(Range(vec![ByteRangeSpec::FromTo(0, 499),
+                                   ByteRangeSpec::AllFrom(9500),
+                                   ByteRangeSpec::Last(500)])),
+                   Header::parse_header(&[b"bytes=0-499, 9500-,-500".to_vec()]));
+        assert_eq!(None::<Range>,
+                   Header::parse_header(&[b"bytes=500-499".to_vec()]));
+        assert_eq!(Some((0, 9)), ByteRangeSpec::FromTo(0, 499).satisfiable_range(10));
+        assert_eq!(Some((5, 9)), ByteRangeSpec::Last(5).satisfiable_range(10));
+        assert_eq!(None, ByteRangeSpec::AllFrom(10).satisfiable_range(10));
+    }
+}
@@ -42,18 +42,37 @@
 //! }
 //! ```
 
+extern crate flate2;
 #[macro_use] extern crate hyper;
 extern crate iron;
 extern crate mime;
+extern crate "rustc-serialize" as rustc_serialize;
 extern crate time;
+extern crate url;
 #[macro_use] extern crate log;
 
+pub use context::RequestContext;
 pub use hyper_headers::*;
 pub use resource::{Resource, ResourceError, ResourceResult};
+pub use typed::{Typed, TypedResource};
 
+/// Authentication
+pub mod auth;
 /// Content Negotiation
 pub mod content_neg;
+/// Per-request context
+pub mod context;
+/// Cross-Origin Resource Sharing
+pub mod cors;
+/// The decision graph
+pub mod graph;
 /// Headers
 pub mod hyper_headers;
+/// PATCH documents
+pub mod patch;
 /// A Resource
 pub mod resource;
+/// Decision tracing
+pub mod trace;
+/// Typed resources
+pub mod typed;
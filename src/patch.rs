@@ -0,0 +1,395 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// PATCH documents
+///
+/// Application of JSON Patch (RFC 6902) and JSON Merge Patch (RFC
+/// 7396) documents to a JSON entity.  A resource's `patch` action can
+/// use `apply` to patch its entity with the request body:
+///
+/// ```ignore
+/// fn patch(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+///     let doc = try!(patch::apply(req, &self.load()));
+///     self.store(doc);
+///     Ok(resp)
+/// }
+/// ```
+use std::collections::BTreeMap;
+use std::{error, fmt};
+
+use iron::{IronError, Request, status};
+use iron::headers;
+use mime::{Mime, TopLevel, SubLevel};
+use rustc_serialize::json::Json;
+
+/// An error applying a patch document.
+#[derive(Debug,PartialEq,Clone)]
+pub enum PatchError {
+    /// The patch document could not be parsed
+    Malformed(String),
+    /// The patch document is not of a supported media type
+    UnsupportedType,
+    /// The patch document is invalid, e.g. an operation is missing a
+    /// required member
+    Invalid(String),
+    /// The patch can not be applied to the current state of the
+    /// entity, e.g. a path does not exist, or a test failed
+    Conflict(String)
+}
+
+impl PatchError {
+    /// Return the status code for the error, as suggested by RFC
+    /// 5789 section 2.2.
+    pub fn status(&self) -> status::Status {
+        match *self {
+            PatchError::Malformed(_) => status::BadRequest,
+            PatchError::UnsupportedType => status::UnsupportedMediaType,
+            PatchError::Invalid(_) => status::UnprocessableEntity,
+            PatchError::Conflict(_) => status::Conflict
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            PatchError::Malformed(ref s) => write!(fmt, "Malformed patch: {}", s),
+            PatchError::UnsupportedType => write!(fmt, "Unsupported patch type"),
+            PatchError::Invalid(ref s) => write!(fmt, "Invalid patch: {}", s),
+            PatchError::Conflict(ref s) => write!(fmt, "Conflict: {}", s)
+        }
+    }
+}
+
+impl error::Error for PatchError {
+    fn description(&self) -> &str {
+        "PatchError"
+    }
+}
+
+impl error::FromError<PatchError> for IronError {
+    fn from_error(err: PatchError) -> IronError {
+        let status = err.status();
+        let body = err.to_string();
+        IronError::new(err, (status, body))
+    }
+}
+
+/// The media type of JSON Patch documents.
+pub fn json_patch_type() -> Mime {
+    Mime(TopLevel::Application, SubLevel::Ext("json-patch+json".to_string()), vec![])
+}
+
+/// The media type of JSON Merge Patch documents.
+pub fn merge_patch_type() -> Mime {
+    Mime(TopLevel::Application, SubLevel::Ext("merge-patch+json".to_string()), vec![])
+}
+
+fn conflict(path: &str) -> PatchError {
+    PatchError::Conflict(format!("path {} does not exist", path))
+}
+
+/// Parse a JSON Pointer, as in RFC 6901, into its reference tokens.
+fn parse_pointer(path: &str) -> Result<Vec<String>, PatchError> {
+    if path.is_empty() {
+        return Ok(vec![]);
+    }
+    if !path.starts_with("/") {
+        return Err(PatchError::Invalid(format!("invalid pointer {}", path)));
+    }
+    Ok(path[1..].split('/')
+       .map(|t| t.replace("~1", "/").replace("~0", "~"))
+       .collect())
+}
+
+/// Return the array index for a reference token.  The `-` token
+/// refers to the end of the array.
+fn array_index(token: &str, len: usize, path: &str) -> Result<usize, PatchError> {
+    if token == "-" {
+        return Ok(len);
+    }
+    if token.len() > 1 && token.starts_with("0") {
+        return Err(conflict(path));
+    }
+    match token.parse::<usize>() {
+        Ok(i) => Ok(i),
+        Err(_) => Err(conflict(path))
+    }
+}
+
+fn resolve<'a>(doc: &'a Json, tokens: &[String],
+               path: &str) -> Result<&'a Json, PatchError> {
+    if tokens.is_empty() {
+        return Ok(doc);
+    }
+    let next = match *doc {
+        Json::Object(ref map) => map.get(&tokens[0]),
+        Json::Array(ref vec) => match array_index(&tokens[0][], vec.len(), path) {
+            Ok(i) => vec.get(i),
+            Err(_) => None
+        },
+        _ => None
+    };
+    match next {
+        Some(child) => resolve(child, &tokens[1..], path),
+        None => Err(conflict(path))
+    }
+}
+
+fn resolve_mut<'a>(doc: &'a mut Json, tokens: &[String],
+                   path: &str) -> Result<&'a mut Json, PatchError> {
+    if tokens.is_empty() {
+        return Ok(doc);
+    }
+    let next = match *doc {
+        Json::Object(ref mut map) => map.get_mut(&tokens[0]),
+        Json::Array(ref mut vec) => {
+            let len = vec.len();
+            match array_index(&tokens[0][], len, path) {
+                Ok(i) => vec.get_mut(i),
+                Err(_) => None
+            }
+        },
+        _ => None
+    };
+    match next {
+        Some(child) => resolve_mut(child, &tokens[1..], path),
+        None => Err(conflict(path))
+    }
+}
+
+fn get(doc: &Json, path: &str) -> Result<Json, PatchError> {
+    let tokens = try!(parse_pointer(path));
+    resolve(doc, &tokens[], path).map(|value| value.clone())
+}
+
+fn add(doc: &mut Json, path: &str, value: Json) -> Result<(), PatchError> {
+    let tokens = try!(parse_pointer(path));
+    if tokens.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let last = tokens.len() - 1;
+    match *try!(resolve_mut(doc, &tokens[..last], path)) {
+        Json::Object(ref mut map) => {
+            map.insert(tokens[last].clone(), value);
+            Ok(())
+        },
+        Json::Array(ref mut vec) => {
+            let i = try!(array_index(&tokens[last][], vec.len(), path));
+            if i > vec.len() {
+                return Err(conflict(path));
+            }
+            vec.insert(i, value);
+            Ok(())
+        },
+        _ => Err(conflict(path))
+    }
+}
+
+fn remove(doc: &mut Json, path: &str) -> Result<Json, PatchError> {
+    let tokens = try!(parse_pointer(path));
+    if tokens.is_empty() {
+        return Err(PatchError::Invalid("cannot remove the whole document".to_string()));
+    }
+    let last = tokens.len() - 1;
+    match *try!(resolve_mut(doc, &tokens[..last], path)) {
+        Json::Object(ref mut map) => map.remove(&tokens[last]).ok_or(conflict(path)),
+        Json::Array(ref mut vec) => {
+            let i = try!(array_index(&tokens[last][], vec.len(), path));
+            if i >= vec.len() {
+                return Err(conflict(path));
+            }
+            Ok(vec.remove(i))
+        },
+        _ => Err(conflict(path))
+    }
+}
+
+fn replace(doc: &mut Json, path: &str, value: Json) -> Result<(), PatchError> {
+    let tokens = try!(parse_pointer(path));
+    *try!(resolve_mut(doc, &tokens[], path)) = value;
+    Ok(())
+}
+
+/// Return the string member `name` of an operation.
+fn member<'a>(op: &'a BTreeMap<String, Json>, name: &str) -> Result<&'a str, PatchError> {
+    match op.get(name) {
+        Some(&Json::String(ref s)) => Ok(&s[]),
+        _ => Err(PatchError::Invalid(format!("operation has no {} member", name)))
+    }
+}
+
+/// Return the `value` member of an operation.
+fn value(op: &BTreeMap<String, Json>) -> Result<Json, PatchError> {
+    op.get("value").map(|v| v.clone())
+        .ok_or(PatchError::Invalid("operation has no value member".to_string()))
+}
+
+fn apply_operation(doc: &mut Json, op: &Json) -> Result<(), PatchError> {
+    let op = match *op {
+        Json::Object(ref op) => op,
+        _ => return Err(PatchError::Invalid("operation is not an object".to_string()))
+    };
+    let path = try!(member(op, "path"));
+    match try!(member(op, "op")) {
+        "add" => add(doc, path, try!(value(op))),
+        "remove" => remove(doc, path).map(|_| ()),
+        "replace" => replace(doc, path, try!(value(op))),
+        "move" => {
+            let from = try!(member(op, "from"));
+            if path.starts_with(&format!("{}/", from)[]) {
+                return Err(PatchError::Invalid(
+                    format!("cannot move {} into itself", from)));
+            }
+            let moved = try!(remove(doc, from));
+            add(doc, path, moved)
+        },
+        "copy" => {
+            let copied = try!(get(doc, try!(member(op, "from"))));
+            add(doc, path, copied)
+        },
+        "test" => {
+            if try!(get(doc, path)) == try!(value(op)) {
+                Ok(())
+            } else {
+                Err(PatchError::Conflict(format!("test of {} failed", path)))
+            }
+        },
+        other => Err(PatchError::Invalid(format!("unknown operation {}", other)))
+    }
+}
+
+/// Apply a JSON Patch document, as specified in RFC 6902, to
+/// `target`.  The patch is applied atomically; if any operation
+/// fails, the error is returned and no result is produced.
+pub fn json_patch(target: &Json, patch: &Json) -> Result<Json, PatchError> {
+    let ops = match *patch {
+        Json::Array(ref ops) => ops,
+        _ => return Err(PatchError::Invalid("patch is not an array".to_string()))
+    };
+    let mut doc = target.clone();
+    for op in ops.iter() {
+        try!(apply_operation(&mut doc, op));
+    }
+    Ok(doc)
+}
+
+/// Apply a JSON Merge Patch document, as specified in RFC 7396, to
+/// `target`.
+pub fn merge_patch(target: &Json, patch: &Json) -> Json {
+    match *patch {
+        Json::Object(ref patch_map) => {
+            let mut map = match *target {
+                Json::Object(ref map) => map.clone(),
+                _ => BTreeMap::new()
+            };
+            for (key, value) in patch_map.iter() {
+                if *value == Json::Null {
+                    map.remove(key);
+                } else {
+                    let merged = match map.get(key) {
+                        Some(existing) => merge_patch(existing, value),
+                        None => merge_patch(&Json::Null, value)
+                    };
+                    map.insert(key.clone(), merged);
+                }
+            }
+            Json::Object(map)
+        },
+        _ => patch.clone()
+    }
+}
+
+/// Apply the patch document in the request body to `target`,
+/// according to the request's Content-Type, which must be
+/// `application/json-patch+json` or `application/merge-patch+json`.
+pub fn apply(req: &mut Request, target: &Json) -> Result<Json, PatchError> {
+    let content_type = match req.headers.get::<headers::ContentType>() {
+        Some(&headers::ContentType(ref ct)) => ct.clone(),
+        None => return Err(PatchError::UnsupportedType)
+    };
+    let body = try!(req.body.read_to_end()
+                    .map_err(|e| PatchError::Malformed(e.to_string())));
+    let body = try!(String::from_utf8(body)
+                    .map_err(|_| PatchError::Malformed("invalid UTF-8".to_string())));
+    let patch = try!(Json::from_str(&body[])
+                     .map_err(|e| PatchError::Malformed(format!("{:?}", e))));
+    match content_type {
+        Mime(TopLevel::Application, SubLevel::Ext(ref s), _)
+            if &s[] == "json-patch+json" => json_patch(target, &patch),
+        Mime(TopLevel::Application, SubLevel::Ext(ref s), _)
+            if &s[] == "merge-patch+json" => Ok(merge_patch(target, &patch)),
+        _ => Err(PatchError::UnsupportedType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::Json;
+
+    fn json(s: &str) -> Json {
+        Json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let doc = json(r#"{"foo": ["bar", "baz"], "qux": {"a/b": 1}}"#);
+        assert_eq!(
+            Ok(json(r#"{"foo": ["bar", "new", "baz", "end"], "qux": {"a/b": 2}}"#)),
+            json_patch(&doc, &json(r#"[
+                {"op": "add", "path": "/foo/1", "value": "new"},
+                {"op": "add", "path": "/foo/-", "value": "end"},
+                {"op": "test", "path": "/qux/a~1b", "value": 1},
+                {"op": "replace", "path": "/qux/a~1b", "value": 2}]"#)));
+        assert_eq!(
+            Ok(json(r#"{"foo": ["baz"], "qux": {"a/b": 1, "c": "bar"}}"#)),
+            json_patch(&doc, &json(r#"[
+                {"op": "move", "from": "/foo/0", "path": "/qux/c"}]"#)));
+        assert_eq!(
+            Err(PatchError::Conflict("test of /foo/0 failed".to_string())),
+            json_patch(&doc, &json(r#"[
+                {"op": "remove", "path": "/qux"},
+                {"op": "test", "path": "/foo/0", "value": "baz"}]"#)));
+        assert_eq!(
+            Err(PatchError::Conflict("path /missing/x does not exist".to_string())),
+            json_patch(&doc, &json(r#"[
+                {"op": "add", "path": "/missing/x", "value": 1}]"#)));
+        assert_eq!(
+            Err(PatchError::Invalid("operation has no value member".to_string())),
+            json_patch(&doc, &json(r#"[{"op": "add", "path": "/x"}]"#)));
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // RFC 7396 section 3
+        let doc = json(r#"{"title": "Goodbye!",
+                           "author": {"givenName": "John", "familyName": "Doe"},
+                           "tags": ["example", "sample"],
+                           "content": "This will be unchanged"}"#);
+        let patch = json(r#"{"title": "Hello!",
+                             "phoneNumber": "+01-123-456-7890",
+                             "author": {"familyName": null},
+                             "tags": ["example"]}"#);
+        assert_eq!(json(r#"{"title": "Hello!",
+                            "author": {"givenName": "John"},
+                            "tags": ["example"],
+                            "content": "This will be unchanged",
+                            "phoneNumber": "+01-123-456-7890"}"#),
+                   merge_patch(&doc, &patch));
+        assert_eq!(json(r#"["c"]"#),
+                   merge_patch(&json(r#"{"a": "b"}"#), &json(r#"["c"]"#)));
+    }
+}
@@ -14,16 +14,26 @@
 
 /// A module for http resources
 use std::{error,fmt};
+use std::ascii::AsciiExt;
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
 
 use hyper::header::EntityTag;
 use iron::{IronError, IronResult, Request, Response, status};
 use iron::headers::{self, Encoding, QualityItem};
 use iron::method;
 use iron::modifier::Set;
-use mime::{Mime, TopLevel, SubLevel};
-use time::Tm;
+use iron::typemap::Key;
+use mime::{Attr, Mime, TopLevel, SubLevel, Value};
+use time::{self, Tm};
+use url::UrlParser;
+use auth;
 use content_neg;
+use cors;
 use hyper_headers;
+use trace;
 
 use self::ResourceError::*;
 
@@ -67,14 +77,33 @@ fn not_implemented(s: &str) -> IronError {
 /// Result type for resource trait functions.
 pub type ResourceResult = Result<(),ResourceError>;
 
-fn strong_match(_: &EntityTag, _: &EntityTag) -> bool {
-    assert!(false,"not implemented");
-    true
+/// Strong comparison of entity tags, as specified in RFC 7232
+/// section 2.3.2.  Both tags must be strong, and their opaque tags
+/// identical.
+fn strong_match(x: &EntityTag, y: &EntityTag) -> bool {
+    !x.weak && !y.weak && x.tag() == y.tag()
+}
+
+/// Weak comparison of entity tags, as specified in RFC 7232 section
+/// 2.3.2.  The opaque tags must be identical, either or both may be
+/// weak.
+fn weak_match(x: &EntityTag, y: &EntityTag) -> bool {
+    x.tag() == y.tag()
+}
+
+/// Key for the entity's etag, once computed for a request.
+struct CachedEtag;
+
+impl Key for CachedEtag {
+    type Value = Option<headers::Etag>;
 }
 
-fn weak_match(_: &EntityTag, _: &EntityTag) -> bool {
-    assert!(false,"not implemented");
-    true
+/// Key for the entity's last modified time, once computed for a
+/// request.
+struct CachedLastModified;
+
+impl Key for CachedLastModified {
+    type Value = Option<Tm>;
 }
 
 /// Cannot use this due to https://github.com/rust-lang/rust/issues/11403
@@ -93,29 +122,49 @@ macro_rules! decision_fn {
         )
 }
 
+/// Call the node following a decision.  The trace is carried over
+/// handlers and actions, so that it is kept on error responses.
+macro_rules! decision_next {
+    ($me:ident, $req:ident, $resp:ident, $next:ident) => (
+        if trace::is_decision(stringify!($next)) {
+            $me.$next($req, $resp)
+        } else {
+            trace::carry($resp, |resp| $me.$next($req, resp))
+        }
+        )
+}
+
 macro_rules! decision_body {
     ($me:ident, $req:ident, $resp:ident,
      $decision:ident, $thenv:ident, $elsev:ident) => (
         {
             let decision=$me.$decision($req, &mut $resp);
             debug!(concat!(stringify!($decision),": {}"), decision);
+            trace::record_decision(&mut $resp, stringify!($decision), decision,
+                                   if decision { stringify!($thenv) }
+                                   else { stringify!($elsev) });
             if decision {
-                $me.$thenv($req,$resp)
+                decision_next!($me, $req, $resp, $thenv)
             } else {
-                $me.$elsev($req,$resp)
+                decision_next!($me, $req, $resp, $elsev)
             }
         }
         );
-    // same, but with an expression rather than an identifier for the decision
+    // same, but with a named expression rather than an identifier for
+    // the decision
     ($me:ident, $req:ident, $resp:ident,
-     $decision:expr, $thenv:ident, $elsev:ident) => (
+     $name:ident: $decision:expr, $thenv:ident, $elsev:ident) => (
         {
+            let mut $resp = $resp;
             let decision=$decision;
-            debug!(concat!(stringify!($decision),": {}"), decision);
+            debug!(concat!(stringify!($name),": {}"), decision);
+            trace::record_decision(&mut $resp, stringify!($name), decision,
+                                   if decision { stringify!($thenv) }
+                                   else { stringify!($elsev) });
             if decision {
-                $me.$thenv($req,$resp)
+                decision_next!($me, $req, $resp, $thenv)
             } else {
-                $me.$elsev($req,$resp)
+                decision_next!($me, $req, $resp, $elsev)
             }
         }
         )
@@ -152,14 +201,13 @@ pub trait Resource : Sync + Send {
         return false;
     }
 
-    /// Override to control valid HTTP verbs for the request.  If this
-    /// returns false, then a 405 MethodNotAllowed reply will result.
-    /// Defaults to alowing GET and HEAD.
-    fn method_allowed(&self, req: &mut Request, _: &mut Response) -> bool {
-        match req.method {
-            method::Get|method::Head => true,
-            _ => false
-        }
+    /// Override to declare the HTTP verbs allowed by the resource.  A
+    /// request with any other verb results in a 405 MethodNotAllowed
+    /// reply.  OPTIONS is always allowed.  These verbs, and OPTIONS,
+    /// are sent in the Allow header of 405 MethodNotAllowed and
+    /// OPTIONS replies.  Defaults to GET and HEAD.
+    fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+        vec![method::Get, method::Head]
     }
 
     /// Override to control request validity.  If this returns false,
@@ -169,16 +217,34 @@ pub trait Resource : Sync + Send {
         false
     }
 
+    /// Override to authenticate the request, using
+    /// `auth::basic_credentials` or `auth::bearer_token` for example.
+    /// A Challenge results in a 401 Unauthorized reply with a
+    /// WWW-Authenticate header, and an Error in a 500
+    /// InternalServerError reply.  Defaults to Anonymous.
+    fn authenticate(&self, _: &mut Request, _: &mut Response) -> auth::Authentication {
+        auth::Authentication::Anonymous
+    }
+
     /// Override to control request authentication.  If this returns
     /// false, then a 401 Unauthorized reply will result.  Defaults to
-    /// true.
-    fn authorized(&self, _: &mut Request, _: &mut Response) -> bool {
-        true
+    /// the outcome of `authenticate`, which is recorded so that the
+    /// principal is available from `auth::principal`.
+    fn authorized(&self, req: &mut Request, resp: &mut Response) -> bool {
+        let authentication = self.authenticate(req, resp);
+        let authorized = match authentication {
+            auth::Authentication::Anonymous |
+            auth::Authentication::Authenticated(_) => true,
+            _ => false
+        };
+        resp.extensions.insert::<auth::Authentication>(authentication);
+        authorized
     }
 
     /// Override to control request authorisation.  If this returns
     /// false, then a 403 Forbidden reply will result.  Defaults to
-    /// true.
+    /// true.  The authenticated principal is available from
+    /// `auth::principal`.
     fn allowed(&self, _: &mut Request, _: &mut Response) -> bool {
         true
     }
@@ -207,7 +273,8 @@ pub trait Resource : Sync + Send {
     /// Override to control whether an entity exists.  If this returns
     /// false, then the entity is deemed not too exist.  This effects
     /// the handling of POST, PUT, and DELETE verbs.  Defaults to
-    /// true.
+    /// true.  An entity loaded here can be kept in the request context
+    /// (see `RequestContext`) for use by later decisions and actions.
     fn exists(&self, _: &mut Request, _: &mut Response) -> bool {
         true
     }
@@ -250,9 +317,9 @@ pub trait Resource : Sync + Send {
         false
     }
 
-    /// Indicates the PUT request has resulted in a conflict.  If this
-    /// returns true, it will cause a 409 Conflict response.  Defaults
-    /// to false.
+    /// Indicates the PUT or PATCH request would result in a conflict.
+    /// If this returns true, it will cause a 409 Conflict response,
+    /// before the action is run.  Defaults to false.
     fn conflict(&self, _: &mut Request, _: &mut Response) -> bool {
         false
     }
@@ -287,8 +354,9 @@ pub trait Resource : Sync + Send {
         false
     }
 
-    /// Indicates whether a delete request was processed.  Defaults to
-    /// true.
+    /// Indicates whether a delete request was enacted.  When false,
+    /// for example if the deletion has only been queued, causes a 202
+    /// Accepted response.  Defaults to true.
     fn delete_enacted(&self, _: &mut Request, _: &mut Response) -> bool {
         true
     }
@@ -299,6 +367,54 @@ pub trait Resource : Sync + Send {
         true
     }
 
+    /// Controls whether the decision trace for the request is added
+    /// to the response as `X-Austenite-Trace` headers.  Defaults to
+    /// false.
+    fn trace_header(&self, _: &Request) -> bool {
+        false
+    }
+
+    /// Controls whether the ETag and Last-Modified headers are set on
+    /// 200, 204, 206 and 304 responses to GET and HEAD requests, and
+    /// on 200, 201 and 204 responses to POST, PUT and PATCH requests,
+    /// from `etag` and `last_modified`.  Defaults to true.
+    fn send_validators(&self, _: &Request, _: &Response) -> bool {
+        true
+    }
+
+    /// Controls whether byte range requests are served for GET
+    /// requests.  When true, `Accept-Ranges: bytes` is sent, and a
+    /// Range header results in a 206 PartialContent or 416
+    /// RangeNotSatisfiable reply.  Defaults to false.
+    fn accept_ranges(&self, _: &Request, _: &Response) -> bool {
+        false
+    }
+
+    /// The largest number of ranges served for a request.  A Range
+    /// header with more ranges is ignored, and the whole body sent, as
+    /// allowed by RFC 7233 section 6.1.  Defaults to 16.
+    fn max_ranges(&self, _: &Request, _: &Response) -> usize {
+        16
+    }
+
+    /// Controls whether the response body is compressed when gzip or
+    /// deflate is the negotiated encoding.  When false, the resource
+    /// is responsible for encoding the body, and for setting the
+    /// Content-Encoding header.  Defaults to true,
+    /// except for media types that are usually already compressed.
+    fn compress(&self, _: &Request, resp: &Response) -> bool {
+        match resp.headers.get::<headers::ContentType>() {
+            Some(&headers::ContentType(ref ct)) => !compressed_media_type(ct),
+            None => true
+        }
+    }
+
+    /// The minimum size of response body, in bytes, that will be
+    /// compressed.  Defaults to 1024.
+    fn compression_threshold(&self, _: &Request, _: &Response) -> usize {
+        1024
+    }
+
     /// Return an optional ETag for the entity
     fn etag(&self, _: &Request, _: &mut Response) -> Option<headers::Etag> {
         None
@@ -340,12 +456,52 @@ pub trait Resource : Sync + Send {
 
     // some data returning methods - not sure these are really wanted
 
+    /// Return the CORS policy of the resource, controlling the
+    /// handling of preflight requests and the Access-Control headers
+    /// of replies to cross-origin requests.  Defaults to None, which
+    /// disables CORS.
+    fn cors(&self, _: &Request, _: &mut Response) -> Option<cors::Cors> {
+        None
+    }
+
+    /// Return the URL of the created or redirected to resource, sent in
+    /// the Location header of 201 Created, 303 SeeOther, 307
+    /// TemporaryRedirect and 308 PermanentRedirect replies.  A
+    /// relative URL is resolved against the request URL.  Defaults to
+    /// None.
+    fn location(&self, _: &Request, _: &mut Response) -> Option<String> {
+        None
+    }
+
+    /// Return the media types accepted in PATCH request bodies, sent
+    /// in the Accept-Patch header of OPTIONS replies.  A PATCH request
+    /// with any other Content-Type results in a 415
+    /// UnsupportedMediaType reply.  Defaults to empty, which accepts
+    /// any patch type.
+    fn accepted_patch_types(&self, _: &Request, _: &mut Response) -> Vec<Mime> {
+        vec![]
+    }
+
+    /// Return the media types accepted in POST request bodies, sent
+    /// in the Accept-Post header of OPTIONS replies.  Defaults to
+    /// empty.
+    fn accepted_post_types(&self, _: &Request, _: &mut Response) -> Vec<Mime> {
+        vec![]
+    }
+
     /// Return a vector of available languages.
     fn available_languages(&self, _: &Request,
                            _: &mut Response) -> Vec<String> {
         vec!["*".to_string()]
     }
 
+    /// Return the language to use when none of the available
+    /// languages is acceptable, instead of a 406 NotAcceptable reply.
+    /// Defaults to None.
+    fn default_language(&self, _: &Request, _: &mut Response) -> Option<String> {
+        None
+    }
+
     /// Return a vector of available charsets.
     fn available_charsets(&self, _: &Request,
                           _: &mut Response) -> Vec<String> {
@@ -364,21 +520,439 @@ pub trait Resource : Sync + Send {
         vec![]
     }
 
+    /// Return a vector of available content types, each with a server
+    /// side quality (qs) between 0 and 1, which is combined with the
+    /// client's quality during negotiation.  Defaults to the types
+    /// returned by `available_content_types`, each with a quality of
+    /// 1.
+    fn weighted_content_types(&self, req: &Request,
+                              resp: &mut Response) -> Vec<QualityItem<Mime>> {
+        self.available_content_types(req, resp).into_iter()
+            .map(|ct| QualityItem{ item: ct, quality: 1.0f32 })
+            .collect()
+    }
+
+    /// Return the variants of the resource.  When not empty, a single
+    /// variant is chosen by `content_neg::negotiate`, considering all
+    /// dimensions together, instead of negotiating each of
+    /// `available_content_types`, `available_languages`,
+    /// `available_charsets` and `available_encodings` separately.
+    /// Defaults to empty.
+    fn variants(&self, _: &Request, _: &mut Response) -> Vec<content_neg::Variant> {
+        vec![]
+    }
+
+    /// Return a vector of request header fields, other than those
+    /// used in content negotiation, that the response varies on,
+    /// e.g. Authorization.
+    fn vary(&self, _: &Request, _: &mut Response) -> Vec<String> {
+        vec![]
+    }
+
 
     /// base logic
 
+    /// Return the entity's ETag.  `etag` is called at most once per
+    /// request, and once more after a POST, PUT or PATCH action.
+    fn entity_etag(&self, req: &Request, resp: &mut Response)
+                   -> Option<headers::Etag> {
+        if let Some(tag) = resp.extensions.get::<CachedEtag>() {
+            return tag.clone();
+        }
+        let tag = self.etag(req, resp);
+        resp.extensions.insert::<CachedEtag>(tag.clone());
+        tag
+    }
+
+    /// Return the entity's last modified time.  `last_modified` is
+    /// called at most once per request, and once more after a POST,
+    /// PUT or PATCH action.
+    fn entity_last_modified(&self, req: &Request, resp: &mut Response)
+                            -> Option<Tm> {
+        if let Some(last_modified) = resp.extensions.get::<CachedLastModified>() {
+            return *last_modified;
+        }
+        let last_modified = self.last_modified(req, resp);
+        resp.extensions.insert::<CachedLastModified>(last_modified);
+        last_modified
+    }
+
+    /// True if the request's If-Range header, if any, matches the
+    /// entity's etag or last modified time, so that the request's
+    /// Range header should be honoured.
+    fn if_range(&self, req: &Request, resp: &mut Response) -> bool {
+        match req.headers.get::<hyper_headers::IfRange>() {
+            Some(&hyper_headers::IfRange::EntityTag(ref tag)) =>
+                match self.entity_etag(req, resp) {
+                    Some(headers::Etag(ref etag)) => strong_match(tag, etag),
+                    None => false
+                },
+            Some(&hyper_headers::IfRange::Date(ref date)) =>
+                match self.entity_last_modified(req, resp) {
+                    Some(ref last_modified) =>
+                        seconds(last_modified) == seconds(date),
+                    None => false
+                },
+            None => true
+        }
+    }
+
+    /// Apply the request's Range header to the 200 Ok response of a
+    /// GET request, when the resource accepts ranges.  Overlapping and
+    /// adjacent ranges are merged, and a header with more than
+    /// `max_ranges` ranges is ignored.
+    fn range_response(&self, req: &Request, mut resp: Response) -> Response {
+        if req.method != method::Get || resp.status != Some(status::Ok) ||
+            !self.accept_ranges(req, &resp) {
+            return resp;
+        }
+        resp.headers.set(
+            hyper_headers::AcceptRanges(vec!["bytes".to_string()]));
+        let specs = match req.headers.get::<hyper_headers::Range>() {
+            Some(&hyper_headers::Range(ref specs)) => specs.clone(),
+            None => return resp
+        };
+        if specs.len() > self.max_ranges(req, &resp) {
+            debug!("range_response ignoring {} ranges", specs.len());
+            return resp;
+        }
+        if !self.if_range(req, &mut resp) {
+            return resp;
+        }
+        let body = take_body(&mut resp);
+        let len = body.len() as u64;
+        let ranges = coalesce(specs.iter()
+                              .filter_map(|spec| spec.satisfiable_range(len))
+                              .collect());
+        debug!("range_response {:?} of {} bytes: {:?}", specs, len, ranges);
+        match ranges.len() {
+            0 => {
+                resp.headers.set(hyper_headers::ContentRange {
+                    range: None,
+                    length: Some(len)
+                });
+                resp.set_mut((status::RangeNotSatisfiable,
+                              "Range not satisfiable"));
+            },
+            1 => {
+                let (first, last) = ranges[0];
+                resp.headers.set(hyper_headers::ContentRange {
+                    range: Some((first, last)),
+                    length: Some(len)
+                });
+                resp.set_mut((status::PartialContent,
+                              body[first as usize..last as usize + 1].to_vec()));
+            },
+            _ => {
+                let boundary = format!("austenite-{:x}", time::precise_time_ns());
+                let content_type = resp.headers.get::<headers::ContentType>()
+                    .map(|ct| ct.0.clone());
+                let parts = multipart_byteranges(&body[], &ranges[], &content_type,
+                                                 &boundary[]);
+                resp.headers.set(headers::ContentType(
+                    Mime(TopLevel::Multipart,
+                         SubLevel::Ext("byteranges".to_string()),
+                         vec![(Attr::Ext("boundary".to_string()),
+                               Value::Ext(boundary))])));
+                resp.set_mut((status::PartialContent, parts));
+            }
+        }
+        resp
+    }
+
+    /// Compress the body of a 200 Ok or 201 Created response when
+    /// gzip or deflate is the negotiated encoding, and set the
+    /// Content-Encoding header.  An ETag set by the resource is made
+    /// weak, as it no longer describes the bytes sent.  Other
+    /// responses, bodies smaller than the compression threshold, and
+    /// responses that already have a Content-Encoding, are sent as
+    /// they are.
+    fn compress_response(&self, req: &Request, mut resp: Response) -> Response {
+        let encoding = match content_neg::negotiated(&resp).encoding {
+            Some(e @ Encoding::Gzip) | Some(e @ Encoding::Deflate) => e,
+            _ => return resp
+        };
+        match resp.status {
+            Some(status::Ok) | Some(status::Created) => (),
+            _ => return resp
+        }
+        if resp.headers.has::<headers::ContentEncoding>() ||
+            !self.compress(req, &resp) {
+            return resp;
+        }
+        let body = take_body(&mut resp);
+        if body.len() < self.compression_threshold(req, &resp) {
+            resp.set_mut(body);
+            return resp;
+        }
+        match compress_body(&encoding, &body[]) {
+            Ok(compressed) => {
+                resp.headers.set(headers::ContentEncoding(vec![encoding]));
+                let tag = resp.headers.get::<headers::Etag>().map(weak_etag);
+                if let Some(tag) = tag {
+                    resp.headers.set(tag);
+                }
+                resp.set_mut(compressed);
+            },
+            Err(e) => {
+                warn!("compress_response failed: {}", e);
+                resp.set_mut(body);
+            }
+        }
+        resp
+    }
+
+    /// Return the request header fields the response varies on.
+    /// These are the negotiation dimensions for which the resource
+    /// offers more than one option, either in its variants or its
+    /// available values, and the fields returned by `vary`.
+    fn vary_fields(&self, req: &Request, resp: &mut Response) -> Vec<String> {
+        let mut fields = vec![];
+        let variants = self.variants(req, resp);
+        if !variants.is_empty() {
+            let v = &variants[0];
+            if variants.iter().any(|x| x.content_type != v.content_type) {
+                fields.push("Accept".to_string());
+            }
+            if variants.iter().any(|x| x.language != v.language) {
+                fields.push("Accept-Language".to_string());
+            }
+            if variants.iter().any(|x| x.charset != v.charset) {
+                fields.push("Accept-Charset".to_string());
+            }
+            if variants.iter().any(|x| x.encoding != v.encoding) {
+                fields.push("Accept-Encoding".to_string());
+            }
+        } else {
+            if self.weighted_content_types(req, resp).len() > 1 {
+                fields.push("Accept".to_string());
+            }
+            if self.available_languages(req, resp).len() > 1 {
+                fields.push("Accept-Language".to_string());
+            }
+            if self.available_charsets(req, resp).len() > 1 {
+                fields.push("Accept-Charset".to_string());
+            }
+            if self.available_encodings(req, resp).len() > 1 {
+                fields.push("Accept-Encoding".to_string());
+            }
+        }
+        match self.cors(req, resp) {
+            Some(ref cors) if cors.varies_on_origin() =>
+                fields.push("Origin".to_string()),
+            _ => ()
+        }
+        for field in self.vary(req, resp).into_iter() {
+            if !fields.iter().any(|f| f.eq_ignore_ascii_case(&field[])) {
+                fields.push(field);
+            }
+        }
+        fields
+    }
+
+    /// Set the Vary header, unless already set by the resource.
+    fn set_vary(&self, req: &Request, resp: &mut Response) {
+        if resp.headers.get_raw("Vary").is_some() {
+            return;
+        }
+        let fields = self.vary_fields(req, resp);
+        if !fields.is_empty() {
+            resp.headers.set_raw("Vary", vec![fields.connect(", ").into_bytes()]);
+        }
+    }
+
+    /// Set the Access-Control headers on the reply to a cross-origin
+    /// request from an allowed origin, unless already set.
+    fn set_cors_headers(&self, req: &Request, resp: &mut Response) {
+        if resp.headers.get_raw("Access-Control-Allow-Origin").is_some() {
+            return;
+        }
+        let origin = match cors::origin(req) {
+            Some(origin) => origin,
+            None => return
+        };
+        match self.cors(req, resp) {
+            Some(ref cors) if cors.allows_origin(&origin[]) =>
+                cors.set_headers(resp, &origin[]),
+            _ => ()
+        }
+    }
+
+    /// True if the body of the response has a content coding, or, for
+    /// a 304 NotModified response, would have been compressed.  The
+    /// entity's ETag is then sent as a weak tag, since a strong
+    /// validator must differ between content codings (RFC 7232
+    /// section 2.3.3).
+    fn encoded_body(&self, req: &Request, resp: &Response) -> bool {
+        if resp.headers.has::<headers::ContentEncoding>() {
+            return true;
+        }
+        resp.status == Some(status::NotModified) &&
+            match content_neg::negotiated(resp).encoding {
+                Some(Encoding::Gzip) | Some(Encoding::Deflate) => true,
+                _ => false
+            } &&
+            self.compress(req, resp)
+    }
+
+    /// Set the ETag and Last-Modified headers on a successful or not
+    /// modified response to a GET or HEAD request, or a successful
+    /// response to a POST, PUT or PATCH request, unless already set by
+    /// the resource.  After those actions the validators are computed
+    /// again, so that they describe the entity as the action left it
+    /// (RFC 7231 section 4.3.4).  No validators are sent after a
+    /// DELETE.
+    fn set_validators(&self, req: &Request, resp: &mut Response) {
+        let statuses = match req.method {
+            method::Get | method::Head =>
+                vec![status::Ok, status::NoContent, status::PartialContent,
+                     status::NotModified],
+            method::Post | method::Put | method::Patch =>
+                vec![status::Ok, status::Created, status::NoContent],
+            _ => vec![]
+        };
+        match resp.status {
+            Some(ref s) if statuses.contains(s) => (),
+            _ => return
+        }
+        if !self.send_validators(req, resp) {
+            return;
+        }
+        if !resp.headers.has::<headers::Etag>() {
+            if let Some(tag) = self.entity_etag(req, resp) {
+                if self.encoded_body(req, resp) {
+                    resp.headers.set(weak_etag(&tag));
+                } else {
+                    resp.headers.set(tag);
+                }
+            }
+        }
+        if !resp.headers.has::<headers::LastModified>() {
+            if let Some(last_modified) = self.entity_last_modified(req, resp) {
+                resp.headers.set(headers::LastModified(last_modified));
+            }
+        }
+    }
+
+    /// Set the Location header from `location`, unless already set by
+    /// the resource.  Returns false if there is no location.
+    fn set_location(&self, req: &Request, resp: &mut Response) -> bool {
+        if resp.headers.has::<headers::Location>() {
+            return true;
+        }
+        match self.location(req, resp) {
+            Some(location) => {
+                resp.headers.set(headers::Location(
+                    resolve_location(req, &location[])));
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Reply with a redirect status and its Location header.  In debug
+    /// builds, a redirect without a location is an error.
+    fn redirect(&self, req: &Request, mut resp: Response,
+                status: status::Status, text: &str) -> IronResult<Response> {
+        if !self.set_location(req, &mut resp) {
+            warn!("{:?} reply without a location", status);
+            if cfg!(not(ndebug)) {
+                return Err(IronError::new(
+                    ApplicationError("redirect without a location".to_string()),
+                    internal_error("Redirect without a location")));
+            }
+        }
+        resp.set_mut(status);
+        if resp.body.is_none() {
+            resp.set_mut(text);
+        }
+        Ok(resp)
+    }
+
+    /// True if the Content-Type of a PATCH request is one of the
+    /// `accepted_patch_types`, or if the resource declares none.
+    fn known_patch_type(&self, req: &mut Request, resp: &mut Response) -> bool {
+        let types = self.accepted_patch_types(req, resp);
+        if types.is_empty() {
+            return true;
+        }
+        match req.headers.get::<headers::ContentType>() {
+            Some(&headers::ContentType(ref ct)) =>
+                types.iter().any(|t| t.0 == ct.0 && t.1 == ct.1),
+            None => false
+        }
+    }
+
+    /// Return the verbs sent in the Allow header: the
+    /// `allowed_methods`, and OPTIONS.
+    fn allow(&self, req: &Request, resp: &mut Response) -> Vec<method::Method> {
+        let mut methods = self.allowed_methods(req, resp);
+        if !methods.contains(&method::Options) {
+            methods.push(method::Options);
+        }
+        methods
+    }
+
+    #[allow(missing_docs)]
+    fn authentication_error(&self, _: &mut Request, resp: &mut Response) -> bool {
+        match auth::authentication(resp) {
+            Some(&auth::Authentication::Error(_)) => true,
+            _ => false
+        }
+    }
+
+    /// True for a CORS preflight request: an OPTIONS request with
+    /// Origin and Access-Control-Request-Method headers, to a resource
+    /// with a CORS policy.
+    fn is_preflight(&self, req: &mut Request, resp: &mut Response) -> bool {
+        req.method == method::Options &&
+            cors::origin(req).is_some() &&
+            cors::request_method(req).is_some() &&
+            self.cors(req, resp).is_some()
+    }
+
+    #[allow(missing_docs)]
+    fn has_variants(&self, req: &mut Request, resp: &mut Response) -> bool {
+        !self.variants(req, resp).is_empty()
+    }
+
+    /// Negotiate a variant from those returned by `variants`.  If one
+    /// is acceptable, the negotiated values are recorded and set on
+    /// the response, and the outcome made available through
+    /// `content_neg::negotiation`.
+    fn variant_available(&self, req: &mut Request, resp: &mut Response) -> bool {
+        let variants = self.variants(req, resp);
+        match content_neg::negotiate(&req.headers, &variants[]) {
+            Some(negotiation) => {
+                let variant = negotiation.variant.clone();
+                if let Some(ct) = variant.content_type {
+                    set_content_type(resp, ct);
+                }
+                if let Some(language) = variant.language {
+                    set_language(resp, language);
+                }
+                if let Some(charset) = variant.charset {
+                    set_charset(resp, charset);
+                }
+                if let Some(encoding) = variant.encoding {
+                    set_encoding(resp, encoding);
+                }
+                resp.extensions.insert::<content_neg::Negotiation>(negotiation);
+                true
+            },
+            None => false
+        }
+    }
+
     #[allow(missing_docs)]
     fn accept_exists(&self, req: &mut Request, resp: &mut Response) -> bool {
         match req.headers.get::<headers::Accept>() {
             Some(_) => true,
             None =>
-                match content_neg::best_content_type(
-                    &vec![QualityItem::<Mime>{
-                        item: Mime(TopLevel::Star, SubLevel::Star, vec![]),
-                        quality: 1.0f32
-                    }],
-                    &self.available_content_types(req,resp)) {
-                    Some(ct) => {resp.set_mut(ct); true}
+                match content_neg::best_weighted_content_type(
+                    &accept_any(),
+                    &self.weighted_content_types(req,resp)) {
+                    Some(ct) => {set_content_type(resp, ct); true}
                     None => false
                 }
         }
@@ -388,35 +962,40 @@ pub trait Resource : Sync + Send {
     fn media_type_available(&self, req: &mut Request, resp: &mut Response) -> bool {
         match req.headers.get::<headers::Accept>() {
             Some(cts) if !cts.is_empty() => {
-                let available = &self.available_content_types(req,resp);
-                match content_neg::best_content_type(&cts, available) {
-                    Some(ct) => {resp.set_mut(ct); true}
+                let available = &self.weighted_content_types(req,resp);
+                match content_neg::best_weighted_content_type(&cts, available) {
+                    Some(ct) => {set_content_type(resp, ct); true}
                     None => false
                 }},
             _ => {
-                let available = self.available_content_types(req,resp);
-                if available.len()>0 {
-                    resp.set_mut(available[0].clone());
+                let available = &self.weighted_content_types(req,resp);
+                match content_neg::best_weighted_content_type(&accept_any(),
+                                                              available) {
+                    Some(ct) => set_content_type(resp, ct),
+                    None => ()
                 }
                 true
             }
         }
     }
 
-
-
     #[allow(missing_docs)]
     fn language_available(&self, req: &mut Request, resp: &mut Response) -> bool {
-        match req.headers.get::<headers::AcceptLanguage>() {
-            Some(_) => match content_neg::best_language(
-                vec![QualityItem::<String>{
-                    item: "*".to_string(),
-                    quality: 1.0f32}],
-                &self.available_languages(req,resp)) {
-                Some(l) => {resp.set_mut(l); true}
+        let accept = match req.headers.get::<headers::AcceptLanguage>() {
+            Some(&headers::AcceptLanguage(ref x)) =>
+                x.iter()
+                .map(|l| QualityItem{ item: l.item.to_string(),
+                                      quality: l.quality })
+                .collect(),
+            None => return true
+        };
+        match content_neg::best_language(
+            accept, &self.available_languages(req,resp)) {
+            Some(l) => {set_language(resp, l); true}
+            None => match self.default_language(req, resp) {
+                Some(l) => {set_language(resp, l); true}
                 None => false
-            },
-            None =>  true
+            }
         }
     }
 
@@ -425,7 +1004,7 @@ pub trait Resource : Sync + Send {
         match req.headers.get::<hyper_headers::AcceptCharset>() {
             Some(&hyper_headers::AcceptCharset(ref x)) => match content_neg::best_charset(
                 x, &self.available_charsets(req,resp)) {
-                Some(l) => {resp.set_mut(l); true}
+                Some(l) => {set_charset(resp, l); true}
                 None => false
             },
             None =>  true
@@ -437,37 +1016,38 @@ pub trait Resource : Sync + Send {
         match req.headers.get::<headers::AcceptEncoding>() {
             Some(&headers::AcceptEncoding(ref x)) => match content_neg::best_encoding(
                 x, &self.available_encodings(req,resp)) {
-                Some(_) => {  // FIXME resp.set_mut(l);
-                    true}
+                Some(e) => {set_encoding(resp, e); true}
                 None => false
             },
             None =>  true
         }
     }
 
-    #[allow(missing_docs)]
+    /// True if the resource's etag strongly matches one of the
+    /// entity tags in the request's If-Match header.
     fn if_match(&self, req: &mut Request, resp: &mut Response) -> bool {
-        match req.headers.get::<headers::Etag>() {
-            Some(&headers::Etag(ref x)) => {
-                match self.etag(req, resp) {
-                    Some(ref tag) => strong_match(x,tag),
-                    None => false
-                }
-            },
-            _ => false
+        let tags = match req.headers.get::<headers::IfMatch>() {
+            Some(&headers::IfMatch::EntityTags(ref tags)) => tags.clone(),
+            _ => return false
+        };
+        match self.entity_etag(req, resp) {
+            Some(headers::Etag(ref tag)) =>
+                tags.iter().any(|x| strong_match(x, tag)),
+            None => false
         }
     }
 
-    #[allow(missing_docs)]
+    /// True if the resource's etag weakly matches one of the entity
+    /// tags in the request's If-None-Match header.
     fn if_none_match(&self, req: &mut Request, resp: &mut Response) -> bool {
-        match req.headers.get::<headers::Etag>() {
-            Some(&headers::Etag(ref x)) => {
-                match self.etag(req, resp) {
-                    Some(ref tag) => weak_match(x,tag),
-                    None => false
-                }
-            },
-            _ => false
+        let tags = match req.headers.get::<headers::IfNoneMatch>() {
+            Some(&headers::IfNoneMatch::EntityTags(ref tags)) => tags.clone(),
+            _ => return false
+        };
+        match self.entity_etag(req, resp) {
+            Some(headers::Etag(ref tag)) =>
+                tags.iter().any(|x| weak_match(x, tag)),
+            None => false
         }
     }
 
@@ -487,12 +1067,15 @@ pub trait Resource : Sync + Send {
         }
     }
 
-    #[allow(missing_docs)]
+    /// True if the entity has been modified since the date in the
+    /// request's If-Unmodified-Since header, which fails the
+    /// precondition.  Dates are compared to the second.
     fn unmodified_since(&self, req: &mut Request, resp: &mut Response) -> bool {
-        match req.headers.get::<headers::IfUnmodifiedSince>() {
-            Some(ref x) => {
-                match self.last_modified(req, resp) {
-                    Some(ref y) => y<=x,
+        match if_unmodified_since_date(req) {
+            Some(ref date) => {
+                match self.entity_last_modified(req, resp) {
+                    Some(ref last_modified) =>
+                        seconds(last_modified) > seconds(date),
                     None => false
                 }
             }
@@ -500,26 +1083,30 @@ pub trait Resource : Sync + Send {
         }
     }
 
-    #[allow(missing_docs)]
+    /// True if the entity has been modified since the date in the
+    /// request's If-Modified-Since header.  An entity without a last
+    /// modified time is always considered modified.  Dates are
+    /// compared to the second.
     fn modified_since(&self, req: &mut Request, resp: &mut Response) -> bool {
-        match req.headers.get::<headers::IfUnmodifiedSince>() {
-            Some(ref x) => {
-                match self.last_modified(req, resp) {
-                    Some(ref y) => y>x,
-                    None => false
+        match if_modified_since_date(req) {
+            Some(ref date) => {
+                match self.entity_last_modified(req, resp) {
+                    Some(ref last_modified) =>
+                        seconds(last_modified) > seconds(date),
+                    None => true
                 }
             }
-            None => false
+            None => true
         }
     }
 
-    #[allow(missing_docs)]
+    /// True if the request has an If-Match header, which fails for a
+    /// missing entity.  A `*` matches no current representation, and
+    /// a tag list evaluates false when there is none (RFC 7232
+    /// section 3.1).
     fn if_match_star_exists_for_missing(&self, req: &mut Request,
                                         _: &mut Response) -> bool {
-        match req.headers.get::<headers::IfMatch>() {
-            Some(&headers::IfMatch::Any) => true,
-            _ => false
-        }
+        header_exists::<headers::IfMatch>(req)
     }
 
     /// logic functions
@@ -542,13 +1129,22 @@ pub trait Resource : Sync + Send {
     fn uri_too_long_decision(&self, req: &mut Request,
                              mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, uri_too_long,
-                       handle_uri_too_long, method_allowed_decision)
+                       handle_uri_too_long, is_preflight_decision)
+    }
+
+    #[allow(missing_docs)]
+    fn is_preflight_decision(&self, req: &mut Request,
+                             mut resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp, is_preflight,
+                       handle_preflight, method_allowed_decision)
     }
 
     #[allow(missing_docs)]
     fn method_allowed_decision(&self, req: &mut Request,
-                               mut resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, method_allowed,
+                               resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp,
+                       method_allowed:
+                       self.allow(req, &mut resp).contains(&req.method),
                        malformed_decision, handle_method_not_allowed)
     }
 
@@ -563,7 +1159,14 @@ pub trait Resource : Sync + Send {
     fn authorized_decision(&self, req: &mut Request,
                            mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, authorized,
-                       allowed_decision, handle_unauthorized)
+                       allowed_decision, authentication_error_decision)
+    }
+
+    #[allow(missing_docs)]
+    fn authentication_error_decision(&self, req: &mut Request,
+                                     mut resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp, authentication_error,
+                       handle_authentication_error, handle_unauthorized)
     }
 
     #[allow(missing_docs)]
@@ -605,8 +1208,24 @@ pub trait Resource : Sync + Send {
     #[allow(missing_docs)]
     fn is_options_decision(&self, req: &mut Request,
                            resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Options,
-                       handle_options, accept_exists_decision)
+        decision_body!(self, req, resp, is_options: req.method == method::Options,
+                       handle_options, has_variants_decision)
+    }
+
+    #[allow(missing_docs)]
+    fn has_variants_decision(&self, req: &mut Request,
+                             mut resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp, has_variants,
+                       variant_available_decision,
+                       accept_exists_decision)
+    }
+
+    #[allow(missing_docs)]
+    fn variant_available_decision(&self, req: &mut Request,
+                                  mut resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp, variant_available,
+                       processable_decision,
+                       handle_not_acceptable)
     }
 
     #[allow(missing_docs)]
@@ -633,6 +1252,7 @@ pub trait Resource : Sync + Send {
                                        resp: Response)
                                        -> IronResult<Response> {
         decision_body!(self, req, resp,
+                       accept_language_exists:
                        header_exists::<headers::AcceptLanguage>(req),
                        language_available_decision,
                        accept_charset_exists_decision)
@@ -654,6 +1274,7 @@ pub trait Resource : Sync + Send {
                                       resp: Response)
                                       -> IronResult<Response> {
         decision_body!(self, req, resp,
+                       accept_charset_exists:
                        header_exists::<hyper_headers::AcceptCharset>(req),
                        charset_available_decision,
                        accept_encoding_exists_decision)
@@ -674,6 +1295,7 @@ pub trait Resource : Sync + Send {
                                        resp: Response)
                                        -> IronResult<Response> {
         decision_body!(self, req, resp,
+                       accept_encoding_exists:
                        header_exists::<headers::AcceptEncoding>(req),
                        encoding_available_decision,
                        processable_decision)
@@ -712,6 +1334,7 @@ pub trait Resource : Sync + Send {
                                 req: &mut Request,
                                 resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp,
+                       if_match_exists:
                        header_exists::<headers::IfMatch>(req),
                        if_match_star_decision,
                        if_unmodified_since_exists_decision)
@@ -741,7 +1364,8 @@ pub trait Resource : Sync + Send {
                                            resp: Response)
                                            -> IronResult<Response> {
         decision_body!(self, req, resp,
-                       header_exists::<headers::IfUnmodifiedSince>(req),
+                       if_unmodified_since_exists:
+                       if_unmodified_since_date(req).is_some(),
                        if_unmodified_since_decision,
                        if_none_match_exists_decision)
     }
@@ -761,6 +1385,7 @@ pub trait Resource : Sync + Send {
                                      resp: Response)
                                      -> IronResult<Response> {
         decision_body!(self, req, resp,
+                       if_none_match_exists:
                        header_exists::<headers::IfNoneMatch>(req),
                        if_none_match_star_decision,
                        if_modified_since_exists_decision)
@@ -770,8 +1395,8 @@ pub trait Resource : Sync + Send {
     fn if_none_match_star_decision(&self, req: &mut Request,
                                    mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, if_none_match_star,
-                       if_none_match_decision,
-                       none_match_status_decision)
+                       none_match_status_decision,
+                       if_none_match_decision)
     }
 
     #[allow(missing_docs)]
@@ -786,6 +1411,7 @@ pub trait Resource : Sync + Send {
     fn none_match_status_decision(&self, req: &mut Request,
                                   resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp,
+                       none_match_status:
                        req.method == method::Get || req.method == method::Head,
                        handle_not_modified,
                        handle_precondition_failed)
@@ -796,7 +1422,8 @@ pub trait Resource : Sync + Send {
                                          resp: Response)
                                          -> IronResult<Response> {
         decision_body!(self, req, resp,
-                       header_exists::<headers::IfModifiedSince>(req),
+                       if_modified_since_exists:
+                       if_modified_since_date(req).is_some(),
                        if_modified_since_decision,
                        method_delete_decision)
     }
@@ -812,31 +1439,48 @@ pub trait Resource : Sync + Send {
     #[allow(missing_docs)]
     fn method_delete_decision(&self, req: &mut Request,
                               resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Delete,
-                       delete,
+        decision_body!(self, req, resp, method_delete: req.method == method::Delete,
+                       delete_action,
                        method_patch_decision)
     }
 
     #[allow(missing_docs)]
     fn method_patch_decision(&self, req: &mut Request,
                              resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Patch,
-                       patch,
+        decision_body!(self, req, resp, method_patch: req.method == method::Patch,
+                       known_patch_type_decision,
                        post_to_existing_decision)
     }
 
+    #[allow(missing_docs)]
+    fn known_patch_type_decision(&self, req: &mut Request,
+                                 mut resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp, known_patch_type,
+                       patch_conflict_decision,
+                       handle_unsupported_patch_type)
+    }
+
+    #[allow(missing_docs)]
+    fn patch_conflict_decision(&self, req: &mut Request,
+                               resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp,
+                       patch_conflict: self.conflict(req, &mut resp),
+                       handle_conflict,
+                       patch_action)
+    }
+
     #[allow(missing_docs)]
     fn post_to_existing_decision(&self, req: &mut Request,
                                  resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Post,
-                       post,
+        decision_body!(self, req, resp, post_to_existing: req.method == method::Post,
+                       post_action,
                        put_to_existing_decision)
     }
 
     #[allow(missing_docs)]
     fn put_to_existing_decision(&self, req: &mut Request,
                                 resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Put,
+        decision_body!(self, req, resp, put_to_existing: req.method == method::Put,
                        conflict_decision,
                        multiple_representations_decision)
     }
@@ -853,7 +1497,7 @@ pub trait Resource : Sync + Send {
     #[allow(missing_docs)]
     fn method_put_decision(&self, req: &mut Request,
                            resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Put,
+        decision_body!(self, req, resp, method_put: req.method == method::Put,
                        put_to_different_url_decision,
                        existed_decision)
     }
@@ -882,7 +1526,7 @@ pub trait Resource : Sync + Send {
                          mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, conflict,
                        handle_conflict,
-                       put)
+                       put_action)
     }
 
     #[allow(missing_docs)]
@@ -915,7 +1559,7 @@ pub trait Resource : Sync + Send {
     #[allow(missing_docs)]
     fn post_to_gone_decision(&self, req: &mut Request,
                              resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Post,
+        decision_body!(self, req, resp, post_to_gone: req.method == method::Post,
                        can_post_to_gone_decision,
                        handle_gone)
     }
@@ -924,14 +1568,14 @@ pub trait Resource : Sync + Send {
     fn can_post_to_gone_decision(&self, req: &mut Request,
                                  mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, can_post_to_gone,
-                       post,
+                       post_action,
                        handle_gone)
     }
 
     #[allow(missing_docs)]
     fn post_to_missing_decision(&self, req: &mut Request,
                                 resp: Response) -> IronResult<Response> {
-        decision_body!(self, req, resp, req.method == method::Post,
+        decision_body!(self, req, resp, post_to_missing: req.method == method::Post,
                        can_post_to_missing_decision,
                        handle_not_found)
     }
@@ -941,10 +1585,19 @@ pub trait Resource : Sync + Send {
                                     req: &mut Request,
                                     mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, can_post_to_missing,
-                       post,
+                       post_action,
                        handle_not_found)
     }
 
+    #[allow(missing_docs)]
+    fn delete_enacted_decision(&self,
+                               req: &mut Request,
+                               mut resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp, delete_enacted,
+                       respond_with_entity_decision,
+                       handle_accepted)
+    }
+
     #[allow(missing_docs)]
     fn post_redirect_decision(&self,
                               req: &mut Request,
@@ -978,7 +1631,58 @@ pub trait Resource : Sync + Send {
                                     mut resp: Response) -> IronResult<Response> {
         decision_body!(self, req, resp, multiple_representations,
                        handle_multiple_representations,
-                       get)
+                       method_get_decision)
+    }
+
+    #[allow(missing_docs)]
+    fn method_get_decision(&self, req: &mut Request,
+                           resp: Response) -> IronResult<Response> {
+        decision_body!(self, req, resp,
+                       method_get:
+                       req.method == method::Get || req.method == method::Head,
+                       get,
+                       handle_ok)
+    }
+
+    // actions
+
+    /// Run the POST action, and on success, continue with the
+    /// post_redirect, new and respond_with_entity decisions to
+    /// determine the status of the response.
+    fn post_action(&self, req: &mut Request,
+                   resp: Response) -> IronResult<Response> {
+        let mut resp = try!(self.post(req, resp));
+        forget_validators(&mut resp);
+        self.post_redirect_decision(req, resp)
+    }
+
+    /// Run the PUT action, and on success, continue with the new and
+    /// respond_with_entity decisions to determine the status of the
+    /// response.
+    fn put_action(&self, req: &mut Request,
+                  resp: Response) -> IronResult<Response> {
+        let mut resp = try!(self.put(req, resp));
+        forget_validators(&mut resp);
+        self.new_decision(req, resp)
+    }
+
+    /// Run the DELETE action, and on success, continue with the
+    /// delete_enacted and respond_with_entity decisions to determine
+    /// the status of the response.
+    fn delete_action(&self, req: &mut Request,
+                     resp: Response) -> IronResult<Response> {
+        let resp = try!(self.delete(req, resp));
+        self.delete_enacted_decision(req, resp)
+    }
+
+    /// Run the PATCH action, and on success, continue with the
+    /// respond_with_entity decision to determine the status of the
+    /// response.
+    fn patch_action(&self, req: &mut Request,
+                    resp: Response) -> IronResult<Response> {
+        let mut resp = try!(self.patch(req, resp));
+        forget_validators(&mut resp);
+        self.respond_with_entity_decision(req, resp)
     }
 
     // default handlers
@@ -1005,8 +1709,10 @@ pub trait Resource : Sync + Send {
     }
 
     #[allow(missing_docs)]
-    fn handle_method_not_allowed(&self, _: &mut Request,
+    fn handle_method_not_allowed(&self, req: &mut Request,
                                  mut resp: Response) -> IronResult<Response> {
+        let methods = self.allow(req, &mut resp);
+        resp.headers.set(headers::Allow(methods));
         resp.set_mut((status::MethodNotAllowed, "Method not allowed"));
         Ok(resp)
     }
@@ -1021,10 +1727,26 @@ pub trait Resource : Sync + Send {
     #[allow(missing_docs)]
     fn handle_unauthorized(&self, _: &mut Request,
                            mut resp: Response) -> IronResult<Response> {
+        let challenges = match auth::authentication(&resp) {
+            Some(&auth::Authentication::Challenge(ref challenges)) =>
+                challenges.clone(),
+            _ => vec![]
+        };
+        auth::set_challenges(&mut resp, &challenges[]);
         resp.set_mut((status::Unauthorized, "Unauthorized"));
         Ok(resp)
     }
 
+    #[allow(missing_docs)]
+    fn handle_authentication_error(&self, _: &mut Request,
+                                   mut resp: Response) -> IronResult<Response> {
+        if let Some(&auth::Authentication::Error(ref e)) = auth::authentication(&resp) {
+            warn!("authentication error: {}", e);
+        }
+        resp.set_mut((status::InternalServerError, "Authentication error"));
+        Ok(resp)
+    }
+
     #[allow(missing_docs)]
     fn handle_forbidden(&self, _: &mut Request,
                         mut resp: Response) -> IronResult<Response> {
@@ -1046,6 +1768,16 @@ pub trait Resource : Sync + Send {
         Ok(resp)
     }
 
+    /// Reply 415 UnsupportedMediaType to a PATCH request, with the
+    /// accepted patch types in an Accept-Patch header.
+    fn handle_unsupported_patch_type(&self, req: &mut Request,
+                                     mut resp: Response) -> IronResult<Response> {
+        let types = self.accepted_patch_types(req, &mut resp);
+        resp.headers.set(hyper_headers::AcceptPatch(types));
+        resp.set_mut((status::UnsupportedMediaType, "Unsupported patch type"));
+        Ok(resp)
+    }
+
     #[allow(missing_docs)]
     fn handle_payload_too_large(&self, _: &mut Request,
                                 mut resp: Response) -> IronResult<Response> {
@@ -1075,17 +1807,41 @@ pub trait Resource : Sync + Send {
         Ok(resp)
     }
 
-    #[allow(missing_docs)]
-    fn handle_see_other(&self, _: &mut Request,
-                        mut resp: Response) -> IronResult<Response> {
-        resp.set_mut((status::SeeOther, "Conflict"));
+    /// Reply 200 Ok to a request other than GET or HEAD, keeping the
+    /// body set by the action.
+    fn handle_ok(&self, _: &mut Request,
+                 mut resp: Response) -> IronResult<Response> {
+        resp.set_mut(status::Ok);
         Ok(resp)
     }
 
-    #[allow(missing_docs)]
-    fn handle_created(&self, _: &mut Request,
+    /// Reply 303 SeeOther, with a Location header from `location`.
+    /// Any body set by the action is kept.
+    fn handle_see_other(&self, req: &mut Request,
+                        resp: Response) -> IronResult<Response> {
+        self.redirect(req, resp, status::SeeOther, "See other")
+    }
+
+    /// Reply 202 Accepted, for a request that has been queued but not
+    /// yet enacted.  Any body set by the action is kept.
+    fn handle_accepted(&self, _: &mut Request,
+                       mut resp: Response) -> IronResult<Response> {
+        resp.set_mut(status::Accepted);
+        if resp.body.is_none() {
+            resp.set_mut("Accepted");
+        }
+        Ok(resp)
+    }
+
+    /// Reply 201 Created, with a Location header from `location`, if
+    /// any.  Any body set by the action is kept.
+    fn handle_created(&self, req: &mut Request,
                       mut resp: Response) -> IronResult<Response> {
-        resp.set_mut((status::Created, "Created"));
+        self.set_location(req, &mut resp);
+        resp.set_mut(status::Created);
+        if resp.body.is_none() {
+            resp.set_mut("Created");
+        }
         Ok(resp)
     }
 
@@ -1103,18 +1859,18 @@ pub trait Resource : Sync + Send {
         Ok(resp)
     }
 
-    #[allow(missing_docs)]
-    fn handle_moved_permanently(&self, _: &mut Request,
-                                mut resp: Response) -> IronResult<Response> {
-        resp.set_mut((status::PermanentRedirect, "Permanent redirect"));
-        Ok(resp)
+    /// Reply 308 PermanentRedirect, with a Location header from
+    /// `location`.
+    fn handle_moved_permanently(&self, req: &mut Request,
+                                resp: Response) -> IronResult<Response> {
+        self.redirect(req, resp, status::PermanentRedirect, "Permanent redirect")
     }
 
-    #[allow(missing_docs)]
-    fn handle_moved_temporarily(&self, _: &mut Request,
-                                mut resp: Response) -> IronResult<Response> {
-        resp.set_mut((status::TemporaryRedirect, "Temporary redirect"));
-        Ok(resp)
+    /// Reply 307 TemporaryRedirect, with a Location header from
+    /// `location`.
+    fn handle_moved_temporarily(&self, req: &mut Request,
+                                resp: Response) -> IronResult<Response> {
+        self.redirect(req, resp, status::TemporaryRedirect, "Temporary redirect")
     }
 
     #[allow(missing_docs)]
@@ -1130,10 +1886,11 @@ pub trait Resource : Sync + Send {
         Ok(resp)
     }
 
-    #[allow(missing_docs)]
+    /// Reply 204 NoContent, discarding any body set by the action.
     fn handle_no_content(&self, _: &mut Request,
                          mut resp: Response) -> IronResult<Response> {
-        resp.set_mut((status::NoContent, "No content"));
+        resp.set_mut(status::NoContent);
+        resp.body = None;
         Ok(resp)
     }
 
@@ -1144,23 +1901,263 @@ pub trait Resource : Sync + Send {
         Ok(resp)
     }
 
+    /// Reply to a CORS preflight request.  The Access-Control headers
+    /// are only set if the origin, method and headers of the request
+    /// are all allowed by the resource's CORS policy.
+    fn handle_preflight(&self, req: &mut Request,
+                        mut resp: Response) -> IronResult<Response> {
+        match (self.cors(req, &mut resp), cors::origin(req)) {
+            (Some(cors), Some(origin)) => {
+                let methods = match cors.methods {
+                    Some(ref methods) => methods.clone(),
+                    None => self.allowed_methods(req, &mut resp)
+                };
+                let allowed =
+                    cors.allows_origin(&origin[]) &&
+                    cors::request_method(req)
+                    .map_or(false, |m| methods.contains(&m)) &&
+                    cors::request_headers(req).iter()
+                    .all(|h| cors.allows_header(&h[]));
+                debug!("handle_preflight {} allowed: {}", origin, allowed);
+                if allowed {
+                    cors.set_preflight_headers(&mut resp, &origin[], &methods[]);
+                }
+            },
+            _ => ()
+        }
+        resp.set_mut((status::Ok, ""));
+        Ok(resp)
+    }
+
     #[allow(missing_docs)]
-    fn handle_options(&self, _: &mut Request,
+    fn handle_options(&self, req: &mut Request,
                       mut resp: Response) -> IronResult<Response> {
+        let methods = self.allow(req, &mut resp);
+        resp.headers.set(headers::Allow(methods));
+        let patch_types = self.accepted_patch_types(req, &mut resp);
+        if !patch_types.is_empty() {
+            resp.headers.set(hyper_headers::AcceptPatch(patch_types));
+        }
+        let post_types = self.accepted_post_types(req, &mut resp);
+        if !post_types.is_empty() {
+            resp.headers.set(hyper_headers::AcceptPost(post_types));
+        }
         resp.set_mut((status::Ok, ""));
         Ok(resp)
     }
 
     /// Iron handler function
     fn resource_handle(&self, req: &mut Request) -> IronResult<Response> {
-        self.service_available_decision(req, Response::new())
+        match self.service_available_decision(req, Response::new()) {
+            Ok(resp) => {
+                let resp = self.range_response(req, resp);
+                let mut resp = self.compress_response(req, resp);
+                self.set_cors_headers(req, &mut resp);
+                self.set_vary(req, &mut resp);
+                self.set_validators(req, &mut resp);
+                if self.trace_header(req) {
+                    trace::set_trace_header(&mut resp);
+                }
+                Ok(resp)
+            },
+            Err(mut e) => {
+                self.set_cors_headers(req, &mut e.response);
+                self.set_vary(req, &mut e.response);
+                if self.trace_header(req) {
+                    trace::set_trace_header(&mut e.response);
+                }
+                Err(e)
+            }
+        }
     }
 }
 
-fn header_exists<T: headers::Header+headers::HeaderFormat>(req: &mut Request) -> bool {
+fn header_exists<T: headers::Header+headers::HeaderFormat>(req: &Request) -> bool {
     req.headers.get::<T>().is_some()
 }
 
+/// An Accept header value that accepts any media type.
+fn accept_any() -> Vec<QualityItem<Mime>> {
+    vec![QualityItem::<Mime>{
+        item: Mime(TopLevel::Star, SubLevel::Star, vec![]),
+        quality: 1.0f32
+    }]
+}
+
+/// Record the negotiated media type, and set the Content-Type header.
+fn set_content_type(resp: &mut Response, content_type: Mime) {
+    content_neg::negotiated_mut(resp).content_type = Some(content_type.clone());
+    resp.set_mut(content_type);
+}
+
+/// Record the negotiated language, and set the Content-Language
+/// header.
+fn set_language(resp: &mut Response, language: String) {
+    if language != "*" {
+        resp.headers.set(hyper_headers::ContentLanguage(vec![language.clone()]));
+    }
+    content_neg::negotiated_mut(resp).language = Some(language);
+}
+
+/// Record the negotiated charset, and add it as the charset parameter
+/// of the Content-Type header.
+fn set_charset(resp: &mut Response, charset: String) {
+    if charset != "*" {
+        if let Some(&mut headers::ContentType(ref mut mime)) =
+            resp.headers.get_mut::<headers::ContentType>() {
+                mime.2.retain(|&(ref attr, _)| *attr != Attr::Charset);
+                mime.2.push((Attr::Charset, Value::Ext(charset.clone())));
+            }
+    }
+    content_neg::negotiated_mut(resp).charset = Some(charset);
+}
+
+/// Record the negotiated content coding.  The Content-Encoding header
+/// is only set once the body has been encoded.
+fn set_encoding(resp: &mut Response, encoding: Encoding) {
+    content_neg::negotiated_mut(resp).encoding = Some(encoding);
+}
+
+/// Return the weak form of an entity tag.
+fn weak_etag(tag: &headers::Etag) -> headers::Etag {
+    headers::Etag(EntityTag::new(true, tag.0.tag().to_string()))
+}
+
+/// True for media types that are usually already compressed, and so
+/// gain nothing from a compressing content coding.
+fn compressed_media_type(ct: &Mime) -> bool {
+    match *ct {
+        Mime(TopLevel::Image, _, _) |
+        Mime(TopLevel::Audio, _, _) |
+        Mime(TopLevel::Video, _, _) => true,
+        Mime(TopLevel::Application, SubLevel::Ext(ref sub), _) =>
+            ["zip", "gzip", "x-gzip", "x-bzip2", "x-xz", "x-7z-compressed",
+             "x-rar-compressed"].contains(&&sub[]),
+        _ => false
+    }
+}
+
+/// Compress a body with the gzip or deflate content coding.
+fn compress_body(encoding: &Encoding, body: &[u8]) -> io::Result<Vec<u8>> {
+    match *encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::Default);
+            try!(encoder.write_all(body));
+            encoder.finish()
+        },
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+            try!(encoder.write_all(body));
+            encoder.finish()
+        },
+        _ => Ok(body.to_vec())
+    }
+}
+
+/// Forget the validators computed before an action that may have
+/// changed the entity, so that those of the changed entity are sent.
+fn forget_validators(resp: &mut Response) {
+    resp.extensions.remove::<CachedEtag>();
+    resp.extensions.remove::<CachedLastModified>();
+}
+
+/// Read and remove the body of a response.
+fn take_body(resp: &mut Response) -> Vec<u8> {
+    match resp.body.take() {
+        Some(mut body) => body.read_to_end().unwrap_or(vec![]),
+        None => vec![]
+    }
+}
+
+/// Sort inclusive byte ranges, and merge those that overlap or are
+/// adjacent, so that no byte of the body is sent more than once.
+fn coalesce(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = vec![];
+    for (first, last) in ranges.into_iter() {
+        match merged.last_mut() {
+            Some(&mut (_, ref mut end)) if first <= *end + 1 => {
+                if last > *end {
+                    *end = last;
+                }
+                continue;
+            },
+            _ => ()
+        }
+        merged.push((first, last));
+    }
+    merged
+}
+
+/// Return a `multipart/byteranges` body containing the given
+/// inclusive byte ranges of `body`.
+fn multipart_byteranges(body: &[u8], ranges: &[(u64, u64)],
+                        content_type: &Option<Mime>,
+                        boundary: &str) -> Vec<u8> {
+    let mut parts = vec![];
+    for &(first, last) in ranges.iter() {
+        parts.push_all(format!("--{}\r\n", boundary).as_bytes());
+        if let Some(ref content_type) = *content_type {
+            parts.push_all(
+                format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        parts.push_all(format!("Content-Range: bytes {}-{}/{}\r\n\r\n",
+                               first, last, body.len()).as_bytes());
+        parts.push_all(&body[first as usize..last as usize + 1]);
+        parts.push_all(b"\r\n");
+    }
+    parts.push_all(format!("--{}--\r\n", boundary).as_bytes());
+    parts
+}
+
+/// Resolve a Location against the request URL, as in RFC 7231
+/// section 7.1.2.  A location that cannot be resolved is returned
+/// unchanged.
+fn resolve_location(req: &Request, location: &str) -> String {
+    let base = req.url.clone().into_generic_url();
+    match UrlParser::new().base_url(&base).parse(location) {
+        Ok(url) => url.serialize(),
+        Err(_) => location.to_string()
+    }
+}
+
+/// HTTP dates have a resolution of one second.
+fn seconds(tm: &Tm) -> i64 {
+    tm.to_timespec().sec
+}
+
+/// Return the date in the request's If-Unmodified-Since header.  The
+/// header is ignored when an If-Match header is present, as specified
+/// in RFC 7232 section 6.  Invalid dates fail to parse, and are also
+/// ignored.
+fn if_unmodified_since_date(req: &Request) -> Option<Tm> {
+    if header_exists::<headers::IfMatch>(req) {
+        return None;
+    }
+    match req.headers.get::<headers::IfUnmodifiedSince>() {
+        Some(&headers::IfUnmodifiedSince(date)) => Some(date),
+        None => None
+    }
+}
+
+/// Return the date in the request's If-Modified-Since header.  The
+/// header is ignored when an If-None-Match header is present, for
+/// methods other than GET and HEAD, and for invalid or future dates,
+/// as specified in RFC 7232 sections 3.3 and 6.
+fn if_modified_since_date(req: &Request) -> Option<Tm> {
+    if header_exists::<headers::IfNoneMatch>(req) {
+        return None;
+    }
+    if req.method != method::Get && req.method != method::Head {
+        return None;
+    }
+    match req.headers.get::<headers::IfModifiedSince>() {
+        Some(&headers::IfModifiedSince(date))
+            if seconds(&date) <= seconds(&time::now_utc()) => Some(date),
+        _ => None
+    }
+}
+
 /// Implement an Iron Handler on a resource
 #[macro_export]
 pub macro_rules! resource_handler {
@@ -1176,9 +2173,29 @@ pub macro_rules! resource_handler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::{coalesce, strong_match, weak_match};
     use hyper::{self, IpAddr};
+    use hyper::header::EntityTag;
     use hyper::server::Listening;
-    use iron::{Handler, Iron, IronResult, Request, Response, status};
+    use iron::{Handler, Iron, IronError, IronResult, Request, Response, status};
+    use iron::headers;
+    use time::{self, Timespec};
+    use hyper_headers::{AcceptCharset, AcceptPatch, ByteRangeSpec, ContentRange, IfRange,
+                        Range};
+    use auth;
+    use content_neg;
+    use context::RequestContext;
+    use cors;
+    use iron::typemap::Key;
+    use std::{error, fmt};
+    use typed::{Typed, TypedResource};
+    use patch;
+    use rustc_serialize::json::Json;
+    use iron::headers::{Encoding, QualityItem};
+    use iron::method;
+    use std::iter::repeat;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use mime::{Attr, Mime, SubLevel, TopLevel, Value};
     use iron::error::HttpResult;
     use iron::modifier::Set;
 
@@ -1188,6 +2205,36 @@ mod tests {
         Iron::new(resource).listen((address,0u16))
     }
 
+    /// Serve a resource, send it a single request, and return the
+    /// status, headers and body of the reply.
+    fn send<T>(resource: T, method: method::Method, headers: headers::Headers,
+               body: &str) -> (status::Status, headers::Headers, String)
+        where T: Resource+Handler+Sync+Send
+    {
+        let mut listen = http_server(resource).unwrap();
+        let mut client = hyper::Client::new();
+        let reply = match client
+            .request(method, &format!("http://127.0.0.1:{}", listen.socket.port)[])
+            .headers(headers)
+            .body(body)
+            .send() {
+                Ok(ref mut r) => (r.status, r.headers.clone(),
+                                  r.read_to_string().unwrap()),
+                Err(e) => panic!("request failed: {:?}", e)
+            };
+        listen.close().unwrap();
+        reply
+    }
+
+    /// Return request headers containing a single header.
+    fn with_header<H>(header: H) -> headers::Headers
+        where H: headers::Header+headers::HeaderFormat
+    {
+        let mut headers = headers::Headers::new();
+        headers.set(header);
+        headers
+    }
+
     // struct GetOk;
     // impl Resource for GetOk {}
     // resource_handler!(GetOk);
@@ -1233,4 +2280,1167 @@ mod tests {
           };
        listen.close().unwrap();
     }
+
+    struct GetTraced;
+    resource_handler!(GetTraced);
+
+    impl Resource for GetTraced {
+        fn trace_header(&self, _: &Request) -> bool {
+            true
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "hello"));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_trace_header() {
+      let mut listen = http_server(GetTraced).unwrap();
+      let mut client = hyper::Client::new();
+       match client.get(&format!("http://127.0.0.1:{}", listen.socket.port)[])
+          .send() {
+              Ok(ref mut r) => {
+                  let trace = r.headers.get_raw("X-Austenite-Trace").unwrap();
+                  assert_eq!(b"decision service_available true".to_vec(),
+                             trace[0]);
+                  assert_eq!(b"handler get".to_vec(), trace[trace.len()-1]);
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    struct GetTracedUnimplemented;
+    resource_handler!(GetTracedUnimplemented);
+
+    impl Resource for GetTracedUnimplemented {
+        fn trace_header(&self, _: &Request) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_trace_header_on_error() {
+      let mut listen = http_server(GetTracedUnimplemented).unwrap();
+      let mut client = hyper::Client::new();
+       match client.get(&format!("http://127.0.0.1:{}", listen.socket.port)[])
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(status::InternalServerError, r.status);
+                  let trace = r.headers.get_raw("X-Austenite-Trace").unwrap();
+                  assert_eq!(b"handler get".to_vec(), trace[trace.len()-1]);
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    #[test]
+    fn test_etag_match() {
+        let strong = EntityTag::new(false, "xyzzy".to_string());
+        let weak = EntityTag::new(true, "xyzzy".to_string());
+        let other = EntityTag::new(false, "other".to_string());
+        assert!(strong_match(&strong, &strong));
+        assert!(!strong_match(&strong, &weak));
+        assert!(!strong_match(&weak, &weak));
+        assert!(!strong_match(&strong, &other));
+        assert!(weak_match(&strong, &weak));
+        assert!(weak_match(&weak, &weak));
+        assert!(!weak_match(&weak, &other));
+    }
+
+    struct GetTagged;
+    resource_handler!(GetTagged);
+
+    impl Resource for GetTagged {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Get, method::Head, method::Put]
+        }
+
+        fn etag(&self, _: &Request, _: &mut Response) -> Option<headers::Etag> {
+            Some(headers::Etag(EntityTag::new(false, "xyzzy".to_string())))
+        }
+
+        fn put(&self, _: &mut Request, resp: Response) -> IronResult<Response> {
+            Ok(resp)
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "hello"));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_if_none_match() {
+      let mut listen = http_server(GetTagged).unwrap();
+      let mut client = hyper::Client::new();
+      let url = format!("http://127.0.0.1:{}", listen.socket.port);
+       match client.get(&url[]).send() {
+              Ok(r) => {
+                  assert_eq!(status::Ok, r.status);
+                  assert_eq!(Some(&headers::Etag(
+                      EntityTag::new(false, "xyzzy".to_string()))),
+                             r.headers.get::<headers::Etag>());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.get(&url[])
+          .header(headers::IfNoneMatch::EntityTags(
+              vec![EntityTag::new(true, "xyzzy".to_string())]))
+          .send() {
+              Ok(r) => assert_eq!(status::NotModified, r.status),
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.get(&url[])
+          .header(headers::IfNoneMatch::EntityTags(
+              vec![EntityTag::new(false, "other".to_string())]))
+          .send() {
+              Ok(r) => assert_eq!(status::Ok, r.status),
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.get(&url[])
+          .header(headers::IfNoneMatch::Any)
+          .send() {
+              Ok(r) => assert_eq!(status::NotModified, r.status),
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.put(&url[])
+          .header(headers::IfNoneMatch::EntityTags(
+              vec![EntityTag::new(false, "other".to_string())]))
+          .body("x")
+          .send() {
+              Ok(r) => assert!(r.status != status::PreconditionFailed,
+                               "put got {}", r.status),
+              Err(_) => assert!(false, "put failed")
+          };
+       match client.put(&url[])
+          .header(headers::IfNoneMatch::EntityTags(
+              vec![EntityTag::new(false, "xyzzy".to_string())]))
+          .body("x")
+          .send() {
+              Ok(r) => assert_eq!(status::PreconditionFailed, r.status),
+              Err(_) => assert!(false, "put failed")
+          };
+       match client.get(&url[])
+          .header(headers::IfMatch::EntityTags(
+              vec![EntityTag::new(false, "other".to_string())]))
+          .send() {
+              Ok(r) => assert_eq!(status::PreconditionFailed, r.status),
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+
+        // any If-Match fails for a missing entity
+        assert_eq!(status::Created,
+                   send(PutMissing, method::Put, headers::Headers::new(), "x").0);
+        assert_eq!(status::PreconditionFailed,
+                   send(PutMissing, method::Put,
+                        with_header(headers::IfMatch::EntityTags(
+                            vec![EntityTag::new(false, "abc".to_string())])),
+                        "x").0);
+        assert_eq!(status::PreconditionFailed,
+                   send(PutMissing, method::Put,
+                        with_header(headers::IfMatch::Any), "x").0);
+    }
+
+    struct PutMissing;
+    resource_handler!(PutMissing);
+
+    impl Resource for PutMissing {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Put]
+        }
+
+        fn exists(&self, _: &mut Request, _: &mut Response) -> bool {
+            false
+        }
+
+        fn put(&self, _: &mut Request, resp: Response) -> IronResult<Response> {
+            Ok(resp)
+        }
+    }
+
+    struct Versioned {
+        version: AtomicUsize
+    }
+    resource_handler!(Versioned);
+
+    impl Resource for Versioned {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Get, method::Head, method::Put, method::Delete]
+        }
+
+        fn etag(&self, _: &Request, _: &mut Response) -> Option<headers::Etag> {
+            let version = self.version.load(Ordering::SeqCst);
+            Some(headers::Etag(EntityTag::new(false, version.to_string())))
+        }
+
+        fn put(&self, _: &mut Request, resp: Response) -> IronResult<Response> {
+            self.version.fetch_add(1, Ordering::SeqCst);
+            Ok(resp)
+        }
+
+        fn delete(&self, _: &mut Request, resp: Response) -> IronResult<Response> {
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_validators_after_put() {
+        // the precondition is evaluated against the entity before the
+        // PUT, and the ETag describes the entity after it
+        let (status, headers, _) =
+            send(Versioned{ version: AtomicUsize::new(1) }, method::Put,
+                 with_header(headers::IfMatch::EntityTags(
+                     vec![EntityTag::new(false, "1".to_string())])),
+                 "x");
+        assert_eq!(status::Created, status);
+        assert_eq!(Some(&headers::Etag(EntityTag::new(false, "2".to_string()))),
+                   headers.get::<headers::Etag>());
+        let (status, headers, _) =
+            send(Versioned{ version: AtomicUsize::new(1) }, method::Delete,
+                 headers::Headers::new(), "");
+        assert_eq!(status::NoContent, status);
+        assert!(headers.get::<headers::Etag>().is_none());
+    }
+
+    struct GetModified;
+    resource_handler!(GetModified);
+
+    impl Resource for GetModified {
+        fn last_modified(&self, _: &Request, _: &Response) -> Option<time::Tm> {
+            Some(time::at_utc(Timespec::new(1420070400, 500000000)))
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "hello"));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let last_modified = time::at_utc(Timespec::new(1420070400, 0));
+        let earlier = time::at_utc(Timespec::new(1420070399, 0));
+        let future = time::at_utc(Timespec::new(
+            time::now_utc().to_timespec().sec + 3600, 0));
+        assert_eq!(status::NotModified,
+                   send(GetModified, method::Get,
+                        with_header(headers::IfModifiedSince(last_modified)), "").0);
+        assert_eq!(status::Ok,
+                   send(GetModified, method::Get,
+                        with_header(headers::IfModifiedSince(earlier)), "").0);
+        assert_eq!(status::Ok,
+                   send(GetModified, method::Get,
+                        with_header(headers::IfModifiedSince(future)), "").0);
+    }
+
+    #[test]
+    fn test_if_unmodified_since() {
+        let last_modified = time::at_utc(Timespec::new(1420070400, 0));
+        let earlier = time::at_utc(Timespec::new(1420070399, 0));
+        assert_eq!(status::Ok,
+                   send(GetModified, method::Get,
+                        with_header(headers::IfUnmodifiedSince(last_modified)), "").0);
+        assert_eq!(status::PreconditionFailed,
+                   send(GetModified, method::Get,
+                        with_header(headers::IfUnmodifiedSince(earlier)), "").0);
+    }
+
+    struct GetRanges;
+    resource_handler!(GetRanges);
+
+    impl Resource for GetRanges {
+        fn accept_ranges(&self, _: &Request, _: &Response) -> bool {
+            true
+        }
+
+        fn etag(&self, _: &Request, _: &mut Response) -> Option<headers::Etag> {
+            Some(headers::Etag(EntityTag::new(false, "xyzzy".to_string())))
+        }
+
+        fn last_modified(&self, _: &Request, _: &Response) -> Option<time::Tm> {
+            Some(time::at_utc(Timespec::new(1420070400, 0)))
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "hello world"));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_range() {
+      let mut listen = http_server(GetRanges).unwrap();
+      let mut client = hyper::Client::new();
+      let url = format!("http://127.0.0.1:{}", listen.socket.port);
+       match client.get(&url[])
+          .header(Range(vec![ByteRangeSpec::FromTo(0, 4)]))
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(status::PartialContent, r.status);
+                  assert_eq!(Some(&ContentRange { range: Some((0, 4)),
+                                                  length: Some(11) }),
+                             r.headers.get::<ContentRange>());
+                  assert_eq!("hello", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.get(&url[])
+          .header(Range(vec![ByteRangeSpec::AllFrom(20)]))
+          .send() {
+              Ok(r) => assert_eq!(status::RangeNotSatisfiable, r.status),
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert_eq!(vec![(0, 6)], coalesce(vec![(2, 6), (0, 4)]));
+        assert_eq!(vec![(0, 10)], coalesce(vec![(0, 4), (5, 10)]));
+        assert_eq!(vec![(0, 4), (6, 10)], coalesce(vec![(6, 10), (0, 4)]));
+        assert_eq!(vec![(0, 10)], coalesce(vec![(0, 10), (0, 10), (3, 4)]));
+    }
+
+    #[test]
+    fn test_multiple_ranges() {
+        let (status, headers, body) =
+            send(GetRanges, method::Get,
+                 with_header(Range(vec![ByteRangeSpec::FromTo(0, 4),
+                                        ByteRangeSpec::FromTo(6, 10)])),
+                 "");
+        assert_eq!(status::PartialContent, status);
+        match headers.get::<headers::ContentType>() {
+            Some(&headers::ContentType(Mime(TopLevel::Multipart,
+                                            SubLevel::Ext(ref sub), _))) =>
+                assert_eq!("byteranges", sub),
+            ct => panic!("unexpected content type {:?}", ct)
+        }
+        assert!(body.contains("Content-Range: bytes 0-4/11\r\n\r\nhello\r\n"));
+        assert!(body.contains("Content-Range: bytes 6-10/11\r\n\r\nworld\r\n"));
+
+        // overlapping ranges are merged into one
+        let (status, headers, body) =
+            send(GetRanges, method::Get,
+                 with_header(Range(vec![ByteRangeSpec::FromTo(0, 4),
+                                        ByteRangeSpec::FromTo(2, 6)])),
+                 "");
+        assert_eq!(status::PartialContent, status);
+        assert_eq!(Some(&ContentRange { range: Some((0, 6)), length: Some(11) }),
+                   headers.get::<ContentRange>());
+        assert_eq!("hello w", body);
+
+        // too many ranges are ignored
+        let (status, _, body) =
+            send(GetRanges, method::Get,
+                 with_header(Range(repeat(ByteRangeSpec::AllFrom(0))
+                                   .take(17).collect())),
+                 "");
+        assert_eq!(status::Ok, status);
+        assert_eq!("hello world", body);
+    }
+
+    fn if_range_status(if_range: IfRange) -> status::Status {
+        let mut headers = with_header(Range(vec![ByteRangeSpec::FromTo(0, 4)]));
+        headers.set(if_range);
+        send(GetRanges, method::Get, headers, "").0
+    }
+
+    #[test]
+    fn test_if_range() {
+        assert_eq!(status::PartialContent,
+                   if_range_status(IfRange::EntityTag(
+                       EntityTag::new(false, "xyzzy".to_string()))));
+        assert_eq!(status::Ok,
+                   if_range_status(IfRange::EntityTag(
+                       EntityTag::new(false, "other".to_string()))));
+        // If-Range uses the strong comparison
+        assert_eq!(status::Ok,
+                   if_range_status(IfRange::EntityTag(
+                       EntityTag::new(true, "xyzzy".to_string()))));
+        assert_eq!(status::PartialContent,
+                   if_range_status(IfRange::Date(
+                       time::at_utc(Timespec::new(1420070400, 0)))));
+        assert_eq!(status::Ok,
+                   if_range_status(IfRange::Date(
+                       time::at_utc(Timespec::new(1420070399, 0)))));
+    }
+
+    struct GetNegotiated;
+    resource_handler!(GetNegotiated);
+
+    impl Resource for GetNegotiated {
+        fn available_content_types(&self, _: &Request,
+                                   _: &mut Response) -> Vec<Mime> {
+            vec![Mime(TopLevel::Text, SubLevel::Plain, vec![])]
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            let charset = content_neg::negotiated(&resp).charset.unwrap();
+            resp.set_mut((status::Ok, charset));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_negotiated_charset() {
+      let mut listen = http_server(GetNegotiated).unwrap();
+      let mut client = hyper::Client::new();
+       match client.get(&format!("http://127.0.0.1:{}", listen.socket.port)[])
+          .header(AcceptCharset(vec![QualityItem{item: "UTF-8".to_string(),
+                                                 quality: 1.0f32}]))
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(Some(&headers::ContentType(
+                      Mime(TopLevel::Text, SubLevel::Plain,
+                           vec![(Attr::Charset, Value::Ext("UTF-8".to_string()))]))),
+                             r.headers.get::<headers::ContentType>());
+                  assert_eq!("UTF-8", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    struct GetCompressed;
+    resource_handler!(GetCompressed);
+
+    impl Resource for GetCompressed {
+        fn available_encodings(&self, _: &Request,
+                               _: &mut Response) -> Vec<Encoding> {
+            vec![Encoding::Gzip, Encoding::Identity]
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, repeat("hello ").take(1000).collect::<String>()));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_gzip_compression() {
+      let mut listen = http_server(GetCompressed).unwrap();
+      let mut client = hyper::Client::new();
+       match client.get(&format!("http://127.0.0.1:{}", listen.socket.port)[])
+          .header(headers::AcceptEncoding(vec![QualityItem{item: Encoding::Gzip,
+                                                           quality: 1.0f32}]))
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(Some(&headers::ContentEncoding(vec![Encoding::Gzip])),
+                             r.headers.get::<headers::ContentEncoding>());
+                  let body = r.read_to_end().unwrap();
+                  assert!(body.len() < 6000);
+                  assert_eq!(&[0x1fu8, 0x8b][], &body[..2]);
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    struct GetCompressedRanges;
+    resource_handler!(GetCompressedRanges);
+
+    impl Resource for GetCompressedRanges {
+        fn available_encodings(&self, _: &Request,
+                               _: &mut Response) -> Vec<Encoding> {
+            vec![Encoding::Gzip, Encoding::Identity]
+        }
+
+        fn etag(&self, _: &Request, _: &mut Response) -> Option<headers::Etag> {
+            Some(headers::Etag(EntityTag::new(false, "xyzzy".to_string())))
+        }
+
+        fn accept_ranges(&self, _: &Request, _: &Response) -> bool {
+            true
+        }
+
+        fn exists(&self, req: &mut Request, _: &mut Response) -> bool {
+            req.url.path[0] != "missing"
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, repeat("hello ").take(1000).collect::<String>()));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_uncompressed_responses() {
+      let mut listen = http_server(GetCompressedRanges).unwrap();
+      let mut client = hyper::Client::new();
+      let url = format!("http://127.0.0.1:{}", listen.socket.port);
+      let gzip = headers::AcceptEncoding(vec![QualityItem{item: Encoding::Gzip,
+                                                          quality: 1.0f32}]);
+       match client.get(&format!("{}/missing", url)[])
+          .header(gzip.clone())
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(status::NotFound, r.status);
+                  assert!(r.headers.get::<headers::ContentEncoding>().is_none());
+                  assert_eq!("Not found", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.get(&url[])
+          .header(gzip.clone())
+          .header(Range(vec![ByteRangeSpec::FromTo(0, 4)]))
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(status::PartialContent, r.status);
+                  assert!(r.headers.get::<headers::ContentEncoding>().is_none());
+                  assert_eq!("hello", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    #[test]
+    fn test_compressed_etag() {
+        let gzip = headers::AcceptEncoding(vec![QualityItem{item: Encoding::Gzip,
+                                                            quality: 1.0f32}]);
+        let strong = headers::Etag(EntityTag::new(false, "xyzzy".to_string()));
+        let weak = headers::Etag(EntityTag::new(true, "xyzzy".to_string()));
+        let (status, headers, _) =
+            send(GetCompressedRanges, method::Get, with_header(gzip.clone()), "");
+        assert_eq!(status::Ok, status);
+        assert_eq!(Some(&weak), headers.get::<headers::Etag>());
+        let (_, headers, _) =
+            send(GetCompressedRanges, method::Get, headers::Headers::new(), "");
+        assert_eq!(Some(&strong), headers.get::<headers::Etag>());
+
+        // resuming a gzip download must not splice identity bytes
+        // into the gzip stream
+        let mut resume = with_header(gzip.clone());
+        resume.set(Range(vec![ByteRangeSpec::FromTo(0, 4)]));
+        resume.set(IfRange::EntityTag(weak.0.clone()));
+        let (status, headers, _) =
+            send(GetCompressedRanges, method::Get, resume, "");
+        assert_eq!(status::Ok, status);
+        assert_eq!(Some(&headers::ContentEncoding(vec![Encoding::Gzip])),
+                   headers.get::<headers::ContentEncoding>());
+
+        let mut revalidate = with_header(gzip.clone());
+        revalidate.set(headers::IfNoneMatch::EntityTags(vec![weak.0.clone()]));
+        let (status, headers, _) =
+            send(GetCompressedRanges, method::Get, revalidate, "");
+        assert_eq!(status::NotModified, status);
+        assert_eq!(Some(&weak), headers.get::<headers::Etag>());
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+      let mut listen = http_server(GetOkContent).unwrap();
+      let mut client = hyper::Client::new();
+       match client.post(&format!("http://127.0.0.1:{}", listen.socket.port)[])
+          .send() {
+              Ok(r) => {
+                  assert_eq!(status::MethodNotAllowed, r.status);
+                  assert_eq!(Some(&headers::Allow(vec![method::Get, method::Head,
+                                                       method::Options])),
+                             r.headers.get::<headers::Allow>());
+              },
+              Err(_) => assert!(false, "post failed")
+          };
+       listen.close().unwrap();
+    }
+
+    #[test]
+    fn test_options() {
+        let (status, headers, _) =
+            send(GetOkContent, method::Options, headers::Headers::new(), "");
+        assert_eq!(status::Ok, status);
+        assert_eq!(Some(&headers::Allow(vec![method::Get, method::Head,
+                                            method::Options])),
+                   headers.get::<headers::Allow>());
+        let (status, headers, _) =
+            send(Patch{ doc: json_doc(), conflict: false }, method::Options,
+                 headers::Headers::new(), "");
+        assert_eq!(status::Ok, status);
+        assert_eq!(Some(&headers::Allow(vec![method::Patch, method::Options])),
+                   headers.get::<headers::Allow>());
+        assert_eq!(Some(&AcceptPatch(vec![patch::json_patch_type(),
+                                         patch::merge_patch_type()])),
+                   headers.get::<AcceptPatch>());
+    }
+
+    struct GetAuthenticated;
+    resource_handler!(GetAuthenticated);
+
+    impl Resource for GetAuthenticated {
+        fn authenticate(&self, req: &mut Request,
+                        _: &mut Response) -> auth::Authentication {
+            match auth::basic_credentials(req) {
+                Some((ref user, Some(ref password)))
+                    if user == "alice" && password == "secret" =>
+                    auth::Authentication::Authenticated(auth::Principal {
+                        name: user.clone(),
+                        scheme: "Basic".to_string()
+                    }),
+                _ => auth::Authentication::Challenge(
+                    vec![auth::Challenge::basic("test")])
+            }
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            let name = auth::principal(&resp).unwrap().name.clone();
+            resp.set_mut((status::Ok, name));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_authentication() {
+      let mut listen = http_server(GetAuthenticated).unwrap();
+      let mut client = hyper::Client::new();
+      let url = format!("http://127.0.0.1:{}", listen.socket.port);
+       match client.get(&url[]).send() {
+              Ok(r) => {
+                  assert_eq!(status::Unauthorized, r.status);
+                  assert_eq!(b"Basic realm=\"test\", charset=\"UTF-8\"".to_vec(),
+                             r.headers.get_raw("WWW-Authenticate").unwrap()[0]);
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.get(&url[])
+          .header(headers::Authorization(headers::Basic {
+              username: "alice".to_string(),
+              password: Some("secret".to_string())
+          }))
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(status::Ok, r.status);
+                  assert_eq!("alice", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    struct CorsResource {
+        exists: bool
+    }
+    resource_handler!(CorsResource);
+
+    impl Resource for CorsResource {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Get, method::Head, method::Put]
+        }
+
+        fn cors(&self, _: &Request, _: &mut Response) -> Option<cors::Cors> {
+            Some(cors::Cors {
+                origins: vec!["https://example.com".to_string()],
+                methods: None,
+                headers: vec!["X-Requested-With".to_string()],
+                expose_headers: vec![],
+                credentials: true,
+                max_age: Some(600)
+            })
+        }
+
+        fn authenticate(&self, req: &mut Request,
+                        _: &mut Response) -> auth::Authentication {
+            match auth::basic_credentials(req) {
+                Some((user, _)) =>
+                    auth::Authentication::Authenticated(auth::Principal {
+                        name: user,
+                        scheme: "Basic".to_string()
+                    }),
+                None => auth::Authentication::Challenge(
+                    vec![auth::Challenge::basic("test")])
+            }
+        }
+
+        fn exists(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.exists
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "hello"));
+            Ok(resp)
+        }
+
+        fn put(&self, _: &mut Request, _: Response) -> IronResult<Response> {
+            Err(IronError::new(ResourceError::ApplicationError("locked".to_string()),
+                               (status::Locked, "Locked")))
+        }
+    }
+
+    /// Return request headers with the given raw values.
+    fn raw_headers(pairs: &[(&'static str, &str)]) -> headers::Headers {
+        let mut headers = headers::Headers::new();
+        for &(name, value) in pairs.iter() {
+            headers.set_raw(name, vec![value.as_bytes().to_vec()]);
+        }
+        headers
+    }
+
+    /// Return the raw value of a reply header.
+    fn raw_value(headers: &headers::Headers, name: &str) -> Option<String> {
+        headers.get_raw(name)
+            .map(|values| String::from_utf8(values.concat()).unwrap())
+    }
+
+    #[test]
+    fn test_cors_preflight() {
+        // a preflight request is not authenticated
+        let (status, headers, _) =
+            send(CorsResource{ exists: true }, method::Options,
+                 raw_headers(&[("Origin", "https://example.com"),
+                               ("Access-Control-Request-Method", "PUT"),
+                               ("Access-Control-Request-Headers",
+                                "X-Requested-With")]),
+                 "");
+        assert_eq!(status::Ok, status);
+        assert_eq!(Some("https://example.com".to_string()),
+                   raw_value(&headers, "Access-Control-Allow-Origin"));
+        assert_eq!(Some("GET, HEAD, PUT".to_string()),
+                   raw_value(&headers, "Access-Control-Allow-Methods"));
+        assert_eq!(Some("X-Requested-With".to_string()),
+                   raw_value(&headers, "Access-Control-Allow-Headers"));
+        assert_eq!(Some("600".to_string()),
+                   raw_value(&headers, "Access-Control-Max-Age"));
+        assert_eq!(Some("true".to_string()),
+                   raw_value(&headers, "Access-Control-Allow-Credentials"));
+
+        let disallowed = [
+            [("Origin", "https://example.org"),
+             ("Access-Control-Request-Method", "PUT"),
+             ("Access-Control-Request-Headers", "X-Requested-With")],
+            [("Origin", "https://example.com"),
+             ("Access-Control-Request-Method", "DELETE"),
+             ("Access-Control-Request-Headers", "X-Requested-With")],
+            [("Origin", "https://example.com"),
+             ("Access-Control-Request-Method", "PUT"),
+             ("Access-Control-Request-Headers", "X-Other")]];
+        for pairs in disallowed.iter() {
+            let (status, headers, _) =
+                send(CorsResource{ exists: true }, method::Options,
+                     raw_headers(&pairs[]), "");
+            assert_eq!(status::Ok, status);
+            assert!(raw_value(&headers, "Access-Control-Allow-Origin").is_none());
+            assert!(raw_value(&headers, "Access-Control-Max-Age").is_none());
+        }
+    }
+
+    #[test]
+    fn test_cors_replies() {
+        let origin = raw_headers(&[("Origin", "https://example.com")]);
+        let mut authorized = origin.clone();
+        authorized.set(headers::Authorization(headers::Basic {
+            username: "alice".to_string(),
+            password: Some("secret".to_string())
+        }));
+        let replies = vec![
+            (status::Ok, send(CorsResource{ exists: true }, method::Get,
+                              authorized.clone(), "")),
+            (status::Unauthorized, send(CorsResource{ exists: true }, method::Get,
+                                        origin.clone(), "")),
+            (status::NotFound, send(CorsResource{ exists: false }, method::Get,
+                                    authorized.clone(), "")),
+            // an action error
+            (status::Locked, send(CorsResource{ exists: true }, method::Put,
+                                  authorized.clone(), "x"))];
+        for &(expected, (status, ref headers, _)) in replies.iter() {
+            assert_eq!(expected, status);
+            assert_eq!(Some("https://example.com".to_string()),
+                       raw_value(headers, "Access-Control-Allow-Origin"));
+            assert_eq!(Some("true".to_string()),
+                       raw_value(headers, "Access-Control-Allow-Credentials"));
+            assert_eq!(Some("Origin".to_string()), raw_value(headers, "Vary"));
+        }
+
+        let (status, headers, _) =
+            send(CorsResource{ exists: true }, method::Get,
+                 raw_headers(&[("Origin", "https://example.org")]), "");
+        assert_eq!(status::Unauthorized, status);
+        assert!(raw_value(&headers, "Access-Control-Allow-Origin").is_none());
+        assert_eq!(Some("Origin".to_string()), raw_value(&headers, "Vary"));
+    }
+
+    struct Loaded;
+    impl Key for Loaded {
+        type Value = String;
+    }
+
+    struct GetFromContext;
+    resource_handler!(GetFromContext);
+
+    impl Resource for GetFromContext {
+        fn exists(&self, req: &mut Request, _: &mut Response) -> bool {
+            req.set_context::<Loaded>("hello".to_string());
+            true
+        }
+
+        fn etag(&self, req: &Request, _: &mut Response) -> Option<headers::Etag> {
+            req.context::<Loaded>()
+                .map(|s| headers::Etag(EntityTag::new(false, s.clone())))
+        }
+
+        fn get(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+            let loaded = req.context::<Loaded>().unwrap().clone();
+            resp.set_mut((status::Ok, loaded));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_context() {
+      let mut listen = http_server(GetFromContext).unwrap();
+      let mut client = hyper::Client::new();
+       match client.get(&format!("http://127.0.0.1:{}", listen.socket.port)[])
+          .send() {
+              Ok(ref mut r) => {
+                  assert_eq!(Some(&headers::Etag(
+                      EntityTag::new(false, "hello".to_string()))),
+                             r.headers.get::<headers::Etag>());
+                  assert_eq!("hello", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       listen.close().unwrap();
+    }
+
+    #[derive(Debug)]
+    enum StoreError {
+        Locked,
+        Resource(ResourceError)
+    }
+
+    impl fmt::Display for StoreError {
+        fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+            write!(fmt, "{:?}", self)
+        }
+    }
+
+    impl error::Error for StoreError {
+        fn description(&self) -> &str {
+            "StoreError"
+        }
+    }
+
+    impl error::FromError<ResourceError> for StoreError {
+        fn from_error(err: ResourceError) -> StoreError {
+            StoreError::Resource(err)
+        }
+    }
+
+    struct Documents {
+        locked: bool
+    }
+
+    impl Resource for Documents {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Get, method::Head, method::Put, method::Delete]
+        }
+    }
+
+    impl TypedResource for Documents {
+        type Entity = String;
+        type Error = StoreError;
+
+        fn load(&self, _: &mut Request, _: &mut Response) -> Option<String> {
+            Some("doc".to_string())
+        }
+
+        fn etag_for(&self, _: &Request, doc: &String) -> Option<headers::Etag> {
+            Some(headers::Etag(EntityTag::new(false, doc.clone())))
+        }
+
+        fn get_entity(&self, _: &mut Request, doc: &String,
+                      mut resp: Response) -> Result<Response, StoreError> {
+            if self.locked {
+                return Err(StoreError::Locked);
+            }
+            resp.set_mut((status::Ok, &doc[]));
+            Ok(resp)
+        }
+
+        fn put_entity(&self, _: &mut Request, doc: Option<&String>,
+                      resp: Response) -> Result<Response, StoreError> {
+            assert_eq!(Some(&"doc".to_string()), doc);
+            if self.locked {
+                return Err(StoreError::Locked);
+            }
+            Ok(resp)
+        }
+
+        fn error_status(&self, err: &StoreError) -> status::Status {
+            match *err {
+                StoreError::Locked => status::Locked,
+                StoreError::Resource(_) => status::InternalServerError
+            }
+        }
+    }
+
+    #[test]
+    fn test_typed_resource() {
+      let mut listen = http_server(Typed(Documents{ locked: false })).unwrap();
+      let mut client = hyper::Client::new();
+      let url = format!("http://127.0.0.1:{}", listen.socket.port);
+       match client.get(&url[]).send() {
+              Ok(ref mut r) => {
+                  assert_eq!(status::Ok, r.status);
+                  assert_eq!(Some(&headers::Etag(
+                      EntityTag::new(false, "doc".to_string()))),
+                             r.headers.get::<headers::Etag>());
+                  assert_eq!("doc", r.read_to_string().unwrap());
+              },
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.put(&url[]).body("x").send() {
+              Ok(r) => assert_eq!(status::Created, r.status),
+              Err(_) => assert!(false, "put failed")
+          };
+       match client.delete(&url[]).send() {
+              Ok(r) => assert_eq!(status::InternalServerError, r.status),
+              Err(_) => assert!(false, "delete failed")
+          };
+       listen.close().unwrap();
+
+      let mut listen = http_server(Typed(Documents{ locked: true })).unwrap();
+      let url = format!("http://127.0.0.1:{}", listen.socket.port);
+       match client.get(&url[]).send() {
+              Ok(r) => assert_eq!(status::Locked, r.status),
+              Err(_) => assert!(false, "get failed")
+          };
+       match client.put(&url[]).body("x").send() {
+              Ok(r) => assert_eq!(status::Locked, r.status),
+              Err(_) => assert!(false, "put failed")
+          };
+       listen.close().unwrap();
+    }
+
+    struct Post {
+        redirect: bool,
+        new: bool,
+        respond_with_entity: bool
+    }
+    resource_handler!(Post);
+
+    impl Resource for Post {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Post]
+        }
+
+        fn post_redirect(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.redirect
+        }
+
+        fn location(&self, _: &Request, _: &mut Response) -> Option<String> {
+            Some("things/1".to_string())
+        }
+
+        fn new(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.new
+        }
+
+        fn respond_with_entity(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.respond_with_entity
+        }
+
+        fn post(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut("posted");
+            Ok(resp)
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "from get"));
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_post_outcomes() {
+        let (status, _, body) = send(Post{ redirect: true, new: true,
+                                           respond_with_entity: false },
+                                     method::Post, headers::Headers::new(), "x");
+        assert_eq!(status::SeeOther, status);
+        assert_eq!("posted", body);
+        let (status, _, body) = send(Post{ redirect: false, new: true,
+                                           respond_with_entity: false },
+                                     method::Post, headers::Headers::new(), "x");
+        assert_eq!(status::Created, status);
+        assert_eq!("posted", body);
+        let (status, _, body) = send(Post{ redirect: false, new: false,
+                                           respond_with_entity: true },
+                                     method::Post, headers::Headers::new(), "x");
+        assert_eq!(status::Ok, status);
+        assert_eq!("posted", body);
+        let (status, _, body) = send(Post{ redirect: false, new: false,
+                                           respond_with_entity: false },
+                                     method::Post, headers::Headers::new(), "x");
+        assert_eq!(status::NoContent, status);
+        assert_eq!("", body);
+    }
+
+    struct Put {
+        new: bool,
+        respond_with_entity: bool
+    }
+    resource_handler!(Put);
+
+    impl Resource for Put {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Put]
+        }
+
+        fn new(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.new
+        }
+
+        fn respond_with_entity(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.respond_with_entity
+        }
+
+        fn put(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut("stored");
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_put_outcomes() {
+        assert_eq!(status::Created,
+                   send(Put{ new: true, respond_with_entity: false },
+                        method::Put, headers::Headers::new(), "x").0);
+        assert_eq!(status::Ok,
+                   send(Put{ new: false, respond_with_entity: true },
+                        method::Put, headers::Headers::new(), "x").0);
+        assert_eq!(status::NoContent,
+                   send(Put{ new: false, respond_with_entity: false },
+                        method::Put, headers::Headers::new(), "x").0);
+    }
+
+    struct Delete {
+        enacted: bool,
+        respond_with_entity: bool
+    }
+    resource_handler!(Delete);
+
+    impl Resource for Delete {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Delete]
+        }
+
+        fn delete_enacted(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.enacted
+        }
+
+        fn respond_with_entity(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.respond_with_entity
+        }
+
+        fn delete(&self, _: &mut Request, resp: Response) -> IronResult<Response> {
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_delete_outcomes() {
+        assert_eq!(status::Accepted,
+                   send(Delete{ enacted: false, respond_with_entity: false },
+                        method::Delete, headers::Headers::new(), "").0);
+        assert_eq!(status::Ok,
+                   send(Delete{ enacted: true, respond_with_entity: true },
+                        method::Delete, headers::Headers::new(), "").0);
+        assert_eq!(status::NoContent,
+                   send(Delete{ enacted: true, respond_with_entity: false },
+                        method::Delete, headers::Headers::new(), "").0);
+    }
+
+    #[test]
+    fn test_location() {
+      let mut listen = http_server(Post{ redirect: false, new: true,
+                                         respond_with_entity: false }).unwrap();
+      let port = listen.socket.port;
+      let mut client = hyper::Client::new();
+       match client.post(&format!("http://127.0.0.1:{}/things/", port)[])
+          .body("x")
+          .send() {
+              Ok(r) => {
+                  assert_eq!(status::Created, r.status);
+                  assert_eq!(Some(&headers::Location(
+                      format!("http://127.0.0.1:{}/things/things/1", port))),
+                             r.headers.get::<headers::Location>());
+              },
+              Err(_) => assert!(false, "post failed")
+          };
+       listen.close().unwrap();
+    }
+
+    struct Patch {
+        doc: Json,
+        conflict: bool
+    }
+    resource_handler!(Patch);
+
+    impl Resource for Patch {
+        fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<method::Method> {
+            vec![method::Patch]
+        }
+
+        fn accepted_patch_types(&self, _: &Request, _: &mut Response) -> Vec<Mime> {
+            vec![patch::json_patch_type(), patch::merge_patch_type()]
+        }
+
+        fn respond_with_entity(&self, _: &mut Request, _: &mut Response) -> bool {
+            true
+        }
+
+        fn conflict(&self, _: &mut Request, _: &mut Response) -> bool {
+            self.conflict
+        }
+
+        fn patch(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+            let doc = try!(patch::apply(req, &self.doc));
+            resp.set_mut(doc.to_string());
+            Ok(resp)
+        }
+
+        fn get(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+            resp.set_mut((status::Ok, "from get"));
+            Ok(resp)
+        }
+    }
+
+    fn json_doc() -> Json {
+        Json::from_str(r#"{"a": 1}"#).unwrap()
+    }
+
+    #[test]
+    fn test_patch() {
+        let (status, headers, _) =
+            send(Patch{ doc: json_doc(), conflict: false }, method::Patch,
+                 with_header(headers::ContentType(
+                     Mime(TopLevel::Application, SubLevel::Json, vec![]))),
+                 r#"{"a": 2}"#);
+        assert_eq!(status::UnsupportedMediaType, status);
+        assert!(headers.get::<AcceptPatch>().is_some());
+        let (status, _, body) =
+            send(Patch{ doc: json_doc(), conflict: false }, method::Patch,
+                 with_header(headers::ContentType(patch::merge_patch_type())),
+                 r#"{"a": 2}"#);
+        assert_eq!(status::Ok, status);
+        assert_eq!(r#"{"a":2}"#, body);
+        assert_eq!(status::Conflict,
+                   send(Patch{ doc: json_doc(), conflict: false }, method::Patch,
+                        with_header(headers::ContentType(patch::json_patch_type())),
+                        r#"[{"op": "test", "path": "/a", "value": 2}]"#).0);
+        assert_eq!(status::UnprocessableEntity,
+                   send(Patch{ doc: json_doc(), conflict: false }, method::Patch,
+                        with_header(headers::ContentType(patch::json_patch_type())),
+                        r#"[{"op": "add", "path": "/b"}]"#).0);
+        // a conflict is found before the patch is applied
+        let (status, _, body) =
+            send(Patch{ doc: json_doc(), conflict: true }, method::Patch,
+                 with_header(headers::ContentType(patch::merge_patch_type())),
+                 r#"{"a": 2}"#);
+        assert_eq!(status::Conflict, status);
+        assert_eq!("Conflict", body);
+    }
 }
@@ -0,0 +1,142 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Decision tracing
+use std::fmt;
+
+use iron::{IronResult, Response};
+use iron::typemap::Key;
+
+/// The name of the response header used to report a decision trace.
+pub const TRACE_HEADER: &'static str = "X-Austenite-Trace";
+
+/// An entry in the decision trace of a request.
+#[derive(Debug,PartialEq,Clone)]
+pub enum TraceEntry {
+    /// A decision node that was visited, and the value it returned
+    Decision(&'static str, bool),
+    /// A handler or action that was run.  Only actions followed by
+    /// further decisions, such as `post_action`, are not the last
+    /// entry of a trace.
+    Handler(&'static str)
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            TraceEntry::Decision(name, value) =>
+                write!(fmt, "decision {} {}", name, value),
+            TraceEntry::Handler(name) =>
+                write!(fmt, "handler {}", name)
+        }
+    }
+}
+
+/// Key for the decision trace in the response extensions.
+pub struct Trace;
+
+impl Key for Trace {
+    type Value = Vec<TraceEntry>;
+}
+
+/// True if `name` is the name of a decision function.
+pub fn is_decision(name: &str) -> bool {
+    name.ends_with("_decision")
+}
+
+fn push(resp: &mut Response, entry: TraceEntry) {
+    if resp.extensions.contains::<Trace>() {
+        resp.extensions.get_mut::<Trace>().unwrap().push(entry);
+    } else {
+        resp.extensions.insert::<Trace>(vec![entry]);
+    }
+}
+
+/// Record the value returned by a decision, and the handler it leads
+/// to, if `next` is not itself a decision.
+pub fn record_decision(resp: &mut Response, name: &'static str,
+                       value: bool, next: &'static str) {
+    push(resp, TraceEntry::Decision(name, value));
+    if !is_decision(next) {
+        record_handler(resp, next);
+    }
+}
+
+/// Record a handler or action that was run.
+pub fn record_handler(resp: &mut Response, name: &'static str) {
+    push(resp, TraceEntry::Handler(name));
+}
+
+/// Call `f`, a handler or action, with `resp`, and keep the trace of
+/// `resp` on the response or error it returns.  An action that fails
+/// returns a new response, that would otherwise have no trace.
+pub fn carry<F>(resp: Response, f: F) -> IronResult<Response>
+    where F: FnOnce(Response) -> IronResult<Response>
+{
+    let entries = resp.extensions.get::<Trace>().map(|t| t.clone());
+    let restore = |resp: &mut Response| {
+        if let Some(ref entries) = entries {
+            if !resp.extensions.contains::<Trace>() {
+                resp.extensions.insert::<Trace>(entries.clone());
+            }
+        }
+    };
+    match f(resp) {
+        Ok(mut resp) => {
+            restore(&mut resp);
+            Ok(resp)
+        },
+        Err(mut e) => {
+            restore(&mut e.response);
+            Err(e)
+        }
+    }
+}
+
+/// Return the decision trace recorded for a response.
+pub fn trace(resp: &Response) -> &[TraceEntry] {
+    match resp.extensions.get::<Trace>() {
+        Some(entries) => &entries[],
+        None => &[]
+    }
+}
+
+/// Add the decision trace to the response as `X-Austenite-Trace`
+/// headers, one header line per entry.
+pub fn set_trace_header(resp: &mut Response) {
+    let lines = trace(resp).iter()
+        .map(|e| e.to_string().into_bytes())
+        .collect::<Vec<Vec<u8>>>();
+    if !lines.is_empty() {
+        resp.headers.set_raw(TRACE_HEADER, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iron::Response;
+
+    #[test]
+    fn test_record_decision() {
+        let mut resp = Response::new();
+        record_decision(&mut resp, "exists", true, "if_match_exists_decision");
+        record_decision(&mut resp, "multiple_representations", false, "get");
+        assert_eq!(&[TraceEntry::Decision("exists", true),
+                     TraceEntry::Decision("multiple_representations", false),
+                     TraceEntry::Handler("get")][],
+                   trace(&resp));
+        assert_eq!("handler get", TraceEntry::Handler("get").to_string());
+    }
+}
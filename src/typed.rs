@@ -0,0 +1,649 @@
+// Copyright 2015 Hugo Duncan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Typed resources
+///
+/// A `TypedResource` loads its entity once, in `load`, and has it
+/// passed to its validators and actions.  Errors returned by the
+/// actions are of the resource's own `Error` type, and are mapped to
+/// a status code by `error_status`.
+///
+/// Wrap a typed resource in `Typed` to serve it.  Every other hook
+/// and handler of the resource's `Resource` implementation is used as
+/// it is.  The untyped entity functions are not ignored: `exists`
+/// must also be true for the entity to exist, `etag` and
+/// `last_modified` are used when `etag_for` and `last_modified_for`
+/// return None, and each typed action defaults to the untyped one:
+///
+/// ```ignore
+/// impl Resource for Documents {
+///     fn allowed_methods(&self, _: &Request, _: &mut Response) -> Vec<Method> {
+///         vec![method::Get, method::Head, method::Put]
+///     }
+/// }
+///
+/// impl TypedResource for Documents {
+///     type Entity = Document;
+///     type Error = StoreError;
+///
+///     fn load(&self, req: &mut Request, _: &mut Response) -> Option<Document> {
+///         self.store.find(req.url.path[0])
+///     }
+///
+///     fn get_entity(&self, _: &mut Request, doc: &Document,
+///                   mut resp: Response) -> Result<Response, StoreError> {
+///         resp.set_mut((status::Ok, doc.render()));
+///         Ok(resp)
+///     }
+/// }
+///
+/// Iron::new(Typed(Documents::new())).listen(address);
+/// ```
+use std::any::Any;
+use std::error::{Error, FromError};
+use std::marker::PhantomData;
+
+use iron::{Handler, IronError, IronResult, Request, Response, status};
+use iron::headers::{self, Encoding, QualityItem};
+use iron::method;
+use iron::modifier::Set;
+use iron::typemap::Key;
+use mime::Mime;
+use time::Tm;
+
+use auth;
+use content_neg;
+use context::RequestContext;
+use cors;
+use resource::{Resource, ResourceError};
+
+/// A resource with a typed entity and error.
+///
+/// Each typed action defaults to the corresponding untyped action of
+/// the `Resource` implementation, whose errors are returned as they
+/// are.  The entity is not in the request context while an action
+/// runs.
+pub trait TypedResource : Resource {
+    /// The entity served by the resource
+    type Entity: Any;
+    /// The application error type of the resource
+    type Error: Error + Send + FromError<ResourceError>;
+
+    /// Load the entity for the request.  Returns None if the entity
+    /// does not exist.
+    fn load(&self, req: &mut Request, resp: &mut Response) -> Option<Self::Entity>;
+
+    /// Return an optional ETag for the entity
+    fn etag_for(&self, _: &Request, _: &Self::Entity) -> Option<headers::Etag> {
+        None
+    }
+
+    /// Return an optional last modified time for the entity
+    fn last_modified_for(&self, _: &Request, _: &Self::Entity) -> Option<Tm> {
+        None
+    }
+
+    /// Execute a GET request for the entity.
+    fn get_entity(&self, req: &mut Request, _: &Self::Entity,
+                  resp: Response) -> Result<Response, Self::Error> {
+        let result = self.get(req, resp);
+        untyped(req, result)
+    }
+
+    /// Execute a POST request, with the entity if it exists.
+    fn post_entity(&self, req: &mut Request, _: Option<&Self::Entity>,
+                   resp: Response) -> Result<Response, Self::Error> {
+        let result = self.post(req, resp);
+        untyped(req, result)
+    }
+
+    /// Execute a PUT request, with the entity if it exists.
+    fn put_entity(&self, req: &mut Request, _: Option<&Self::Entity>,
+                  resp: Response) -> Result<Response, Self::Error> {
+        let result = self.put(req, resp);
+        untyped(req, result)
+    }
+
+    /// Execute a DELETE request for the entity.
+    fn delete_entity(&self, req: &mut Request, _: &Self::Entity,
+                     resp: Response) -> Result<Response, Self::Error> {
+        let result = self.delete(req, resp);
+        untyped(req, result)
+    }
+
+    /// Execute a PATCH request for the entity.
+    fn patch_entity(&self, req: &mut Request, _: &Self::Entity,
+                    resp: Response) -> Result<Response, Self::Error> {
+        let result = self.patch(req, resp);
+        untyped(req, result)
+    }
+
+    /// Return the status code for an application error.  Defaults to
+    /// 500 InternalServerError.
+    fn error_status(&self, _: &Self::Error) -> status::Status {
+        status::InternalServerError
+    }
+}
+
+/// Key for a typed resource's entity in the request context.
+pub struct EntityKey<R>(PhantomData<R>);
+
+impl<R: TypedResource + Any> Key for EntityKey<R> {
+    type Value = Option<R::Entity>;
+}
+
+/// Return the entity loaded for the request, if any.
+pub fn entity<R: TypedResource + Any>(req: &Request) -> Option<&R::Entity> {
+    match req.context::<EntityKey<R>>() {
+        Some(&Some(ref entity)) => Some(entity),
+        _ => None
+    }
+}
+
+/// Load the entity, keeping it in the request context, and return
+/// true if it exists.  The entity is loaded at most once per request.
+fn exists<R: TypedResource + Any>(resource: &R, req: &mut Request,
+                                  resp: &mut Response) -> bool {
+    req.context_or_insert_with::<EntityKey<R>, _>(|req| resource.load(req, resp))
+        .is_some()
+}
+
+/// Take the entity out of the request context, loading it if needed.
+/// The entity is taken, as the request is borrowed mutably by the
+/// actions it is passed to.
+fn take_entity<R: TypedResource + Any>(resource: &R, req: &mut Request,
+                                       resp: &mut Response) -> Option<R::Entity> {
+    exists(resource, req, resp);
+    req.extensions.remove::<EntityKey<R>>().and_then(|entity| entity)
+}
+
+/// Key for the error of an untyped action run by a typed action.
+struct UntypedError;
+
+impl Key for UntypedError {
+    type Value = IronError;
+}
+
+/// Return the result of an untyped action from a typed action.  An
+/// error is kept in the request, so that `map_error` returns it as it
+/// is, rather than mapping it through the resource's error type.
+fn untyped<E: FromError<ResourceError>>(req: &mut Request,
+                                        result: IronResult<Response>)
+                                        -> Result<Response, E> {
+    result.map_err(|e| {
+        let err = ResourceError::ApplicationError(e.error.description().to_string());
+        req.extensions.insert::<UntypedError>(e);
+        FromError::from_error(err)
+    })
+}
+
+/// Map an application error to an IronError, with the status given
+/// by the resource's `error_status`.
+fn map_error<R: TypedResource>(resource: &R, req: &mut Request,
+                               result: Result<Response, R::Error>)
+                               -> IronResult<Response> {
+    result.map_err(|e| {
+        if let Some(err) = req.extensions.remove::<UntypedError>() {
+            return err;
+        }
+        let status = resource.error_status(&e);
+        let body = e.description().to_string();
+        IronError::new(e, (status, body))
+    })
+}
+
+/// Load the entity again after a successful action that may have
+/// changed it, so that the validators describe the changed entity.
+fn reload<R: TypedResource + Any>(resource: &R, req: &mut Request,
+                                  result: IronResult<Response>)
+                                  -> IronResult<Response> {
+    result.map(|mut resp| {
+        exists(resource, req, &mut resp);
+        resp
+    })
+}
+
+fn not_found(mut resp: Response) -> IronResult<Response> {
+    resp.set_mut((status::NotFound, "Not found"));
+    Ok(resp)
+}
+
+/// Serve a `TypedResource`.
+pub struct Typed<R>(pub R);
+
+impl<R: TypedResource + Any> Handler for Typed<R> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.resource_handle(req)
+    }
+}
+
+impl<R: TypedResource + Any> Resource for Typed<R> {
+    fn exists(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.exists(req, resp) && exists(&self.0, req, resp)
+    }
+
+    fn etag(&self, req: &Request, resp: &mut Response) -> Option<headers::Etag> {
+        match entity::<R>(req).and_then(|e| self.0.etag_for(req, e)) {
+            Some(tag) => Some(tag),
+            None => self.0.etag(req, resp)
+        }
+    }
+
+    fn last_modified(&self, req: &Request, resp: &Response) -> Option<Tm> {
+        match entity::<R>(req).and_then(|e| self.0.last_modified_for(req, e)) {
+            Some(last_modified) => Some(last_modified),
+            None => self.0.last_modified(req, resp)
+        }
+    }
+
+    fn get(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        match take_entity(&self.0, req, &mut resp) {
+            Some(entity) => {
+                let result = self.0.get_entity(req, &entity, resp);
+                // a GET leaves the entity unchanged, so keep it for
+                // the validators
+                req.set_context::<EntityKey<R>>(Some(entity));
+                map_error(&self.0, req, result)
+            },
+            None => not_found(resp)
+        }
+    }
+
+    fn post(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        let entity = take_entity(&self.0, req, &mut resp);
+        let result = self.0.post_entity(req, entity.as_ref(), resp);
+        reload(&self.0, req, map_error(&self.0, req, result))
+    }
+
+    fn put(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        let entity = take_entity(&self.0, req, &mut resp);
+        let result = self.0.put_entity(req, entity.as_ref(), resp);
+        reload(&self.0, req, map_error(&self.0, req, result))
+    }
+
+    fn delete(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        match take_entity(&self.0, req, &mut resp) {
+            Some(entity) => {
+                let result = self.0.delete_entity(req, &entity, resp);
+                map_error(&self.0, req, result)
+            },
+            None => not_found(resp)
+        }
+    }
+
+    fn patch(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        match take_entity(&self.0, req, &mut resp) {
+            Some(entity) => {
+                let result = self.0.patch_entity(req, &entity, resp);
+                reload(&self.0, req, map_error(&self.0, req, result))
+            },
+            None => not_found(resp)
+        }
+    }
+
+    // the remaining hooks and handlers are those of the wrapped resource
+
+    fn service_available(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.service_available(req, resp)
+    }
+
+    fn known_method(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.known_method(req, resp)
+    }
+
+    fn uri_too_long(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.uri_too_long(req, resp)
+    }
+
+    fn allowed_methods(&self, req: &Request, resp: &mut Response) -> Vec<method::Method> {
+        self.0.allowed_methods(req, resp)
+    }
+
+    fn malformed(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.malformed(req, resp)
+    }
+
+    fn authenticate(&self, req: &mut Request,
+                    resp: &mut Response) -> auth::Authentication {
+        self.0.authenticate(req, resp)
+    }
+
+    fn authorized(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.authorized(req, resp)
+    }
+
+    fn allowed(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.allowed(req, resp)
+    }
+
+    fn valid_content_header(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.valid_content_header(req, resp)
+    }
+
+    fn known_content_type(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.known_content_type(req, resp)
+    }
+
+    fn valid_entity_length(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.valid_entity_length(req, resp)
+    }
+
+    fn existed(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.existed(req, resp)
+    }
+
+    fn respond_with_entity(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.respond_with_entity(req, resp)
+    }
+
+    fn new(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.new(req, resp)
+    }
+
+    fn post_redirect(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.post_redirect(req, resp)
+    }
+
+    fn put_to_different_url(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.put_to_different_url(req, resp)
+    }
+
+    fn multiple_representations(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.multiple_representations(req, resp)
+    }
+
+    fn conflict(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.conflict(req, resp)
+    }
+
+    fn can_post_to_missing(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.can_post_to_missing(req, resp)
+    }
+
+    fn can_post_to_gone(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.can_post_to_gone(req, resp)
+    }
+
+    fn can_put_to_missing(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.can_put_to_missing(req, resp)
+    }
+
+    fn moved_permanently(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.moved_permanently(req, resp)
+    }
+
+    fn moved_temporarily(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.moved_temporarily(req, resp)
+    }
+
+    fn delete_enacted(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.delete_enacted(req, resp)
+    }
+
+    fn processable(&self, req: &mut Request, resp: &mut Response) -> bool {
+        self.0.processable(req, resp)
+    }
+
+    fn trace_header(&self, req: &Request) -> bool {
+        self.0.trace_header(req)
+    }
+
+    fn send_validators(&self, req: &Request, resp: &Response) -> bool {
+        self.0.send_validators(req, resp)
+    }
+
+    fn accept_ranges(&self, req: &Request, resp: &Response) -> bool {
+        self.0.accept_ranges(req, resp)
+    }
+
+    fn max_ranges(&self, req: &Request, resp: &Response) -> usize {
+        self.0.max_ranges(req, resp)
+    }
+
+    fn compress(&self, req: &Request, resp: &Response) -> bool {
+        self.0.compress(req, resp)
+    }
+
+    fn compression_threshold(&self, req: &Request, resp: &Response) -> usize {
+        self.0.compression_threshold(req, resp)
+    }
+
+    fn cors(&self, req: &Request, resp: &mut Response) -> Option<cors::Cors> {
+        self.0.cors(req, resp)
+    }
+
+    fn location(&self, req: &Request, resp: &mut Response) -> Option<String> {
+        self.0.location(req, resp)
+    }
+
+    fn accepted_patch_types(&self, req: &Request, resp: &mut Response) -> Vec<Mime> {
+        self.0.accepted_patch_types(req, resp)
+    }
+
+    fn accepted_post_types(&self, req: &Request, resp: &mut Response) -> Vec<Mime> {
+        self.0.accepted_post_types(req, resp)
+    }
+
+    fn available_languages(&self, req: &Request, resp: &mut Response) -> Vec<String> {
+        self.0.available_languages(req, resp)
+    }
+
+    fn default_language(&self, req: &Request, resp: &mut Response) -> Option<String> {
+        self.0.default_language(req, resp)
+    }
+
+    fn available_charsets(&self, req: &Request, resp: &mut Response) -> Vec<String> {
+        self.0.available_charsets(req, resp)
+    }
+
+    fn available_encodings(&self, req: &Request, resp: &mut Response) -> Vec<Encoding> {
+        self.0.available_encodings(req, resp)
+    }
+
+    fn available_content_types(&self, req: &Request, resp: &mut Response) -> Vec<Mime> {
+        self.0.available_content_types(req, resp)
+    }
+
+    fn weighted_content_types(&self, req: &Request,
+                              resp: &mut Response) -> Vec<QualityItem<Mime>> {
+        self.0.weighted_content_types(req, resp)
+    }
+
+    fn variants(&self, req: &Request, resp: &mut Response) -> Vec<content_neg::Variant> {
+        self.0.variants(req, resp)
+    }
+
+    fn vary(&self, req: &Request, resp: &mut Response) -> Vec<String> {
+        self.0.vary(req, resp)
+    }
+
+    // handlers
+
+    fn handle_service_unavailable(&self, req: &mut Request,
+                                  resp: Response) -> IronResult<Response> {
+        self.0.handle_service_unavailable(req, resp)
+    }
+
+    fn handle_unknown_method(&self, req: &mut Request,
+                             resp: Response) -> IronResult<Response> {
+        self.0.handle_unknown_method(req, resp)
+    }
+
+    fn handle_uri_too_long(&self, req: &mut Request,
+                           resp: Response) -> IronResult<Response> {
+        self.0.handle_uri_too_long(req, resp)
+    }
+
+    fn handle_method_not_allowed(&self, req: &mut Request,
+                                 resp: Response) -> IronResult<Response> {
+        self.0.handle_method_not_allowed(req, resp)
+    }
+
+    fn handle_malformed(&self, req: &mut Request,
+                        resp: Response) -> IronResult<Response> {
+        self.0.handle_malformed(req, resp)
+    }
+
+    fn handle_unauthorized(&self, req: &mut Request,
+                           resp: Response) -> IronResult<Response> {
+        self.0.handle_unauthorized(req, resp)
+    }
+
+    fn handle_authentication_error(&self, req: &mut Request,
+                                   resp: Response) -> IronResult<Response> {
+        self.0.handle_authentication_error(req, resp)
+    }
+
+    fn handle_forbidden(&self, req: &mut Request,
+                        resp: Response) -> IronResult<Response> {
+        self.0.handle_forbidden(req, resp)
+    }
+
+    fn handle_not_implemented(&self, req: &mut Request,
+                              resp: Response) -> IronResult<Response> {
+        self.0.handle_not_implemented(req, resp)
+    }
+
+    fn handle_unsupported_media_type(&self, req: &mut Request,
+                                     resp: Response) -> IronResult<Response> {
+        self.0.handle_unsupported_media_type(req, resp)
+    }
+
+    fn handle_unsupported_patch_type(&self, req: &mut Request,
+                                     resp: Response) -> IronResult<Response> {
+        self.0.handle_unsupported_patch_type(req, resp)
+    }
+
+    fn handle_payload_too_large(&self, req: &mut Request,
+                                resp: Response) -> IronResult<Response> {
+        self.0.handle_payload_too_large(req, resp)
+    }
+
+    fn handle_not_acceptable(&self, req: &mut Request,
+                             resp: Response) -> IronResult<Response> {
+        self.0.handle_not_acceptable(req, resp)
+    }
+
+    fn handle_unprocessable_entity(&self, req: &mut Request,
+                                   resp: Response) -> IronResult<Response> {
+        self.0.handle_unprocessable_entity(req, resp)
+    }
+
+    fn handle_conflict(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        self.0.handle_conflict(req, resp)
+    }
+
+    fn handle_ok(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        self.0.handle_ok(req, resp)
+    }
+
+    fn handle_see_other(&self, req: &mut Request,
+                        resp: Response) -> IronResult<Response> {
+        self.0.handle_see_other(req, resp)
+    }
+
+    fn handle_accepted(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        self.0.handle_accepted(req, resp)
+    }
+
+    fn handle_created(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        self.0.handle_created(req, resp)
+    }
+
+    fn handle_precondition_failed(&self, req: &mut Request,
+                                  resp: Response) -> IronResult<Response> {
+        self.0.handle_precondition_failed(req, resp)
+    }
+
+    fn handle_not_modified(&self, req: &mut Request,
+                           resp: Response) -> IronResult<Response> {
+        self.0.handle_not_modified(req, resp)
+    }
+
+    fn handle_moved_permanently(&self, req: &mut Request,
+                                resp: Response) -> IronResult<Response> {
+        self.0.handle_moved_permanently(req, resp)
+    }
+
+    fn handle_moved_temporarily(&self, req: &mut Request,
+                                resp: Response) -> IronResult<Response> {
+        self.0.handle_moved_temporarily(req, resp)
+    }
+
+    fn handle_gone(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        self.0.handle_gone(req, resp)
+    }
+
+    fn handle_not_found(&self, req: &mut Request,
+                        resp: Response) -> IronResult<Response> {
+        self.0.handle_not_found(req, resp)
+    }
+
+    fn handle_no_content(&self, req: &mut Request,
+                         resp: Response) -> IronResult<Response> {
+        self.0.handle_no_content(req, resp)
+    }
+
+    fn handle_multiple_representations(&self, req: &mut Request,
+                                       resp: Response) -> IronResult<Response> {
+        self.0.handle_multiple_representations(req, resp)
+    }
+
+    fn handle_preflight(&self, req: &mut Request,
+                        resp: Response) -> IronResult<Response> {
+        self.0.handle_preflight(req, resp)
+    }
+
+    fn handle_options(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        self.0.handle_options(req, resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /// Return the names of the functions defined at the first level of
+    /// indentation of `src`.
+    fn fn_names(src: &str) -> Vec<String> {
+        src.lines()
+            .filter(|l| l.starts_with("    fn "))
+            .map(|l| l["    fn ".len()..].split('(').next().unwrap().to_string())
+            .collect()
+    }
+
+    /// Return the text of the block starting at `start`, up to its
+    /// closing brace at the start of a line.
+    fn block<'a>(src: &'a str, start: &str) -> &'a str {
+        let first = src.find(start).unwrap();
+        let last = first + src[first..].find("\n}\n").unwrap();
+        &src[first..last]
+    }
+
+    #[test]
+    fn test_typed_forwards_resource() {
+        // the hooks, before the base logic, and the handlers of
+        // Resource must all be implemented by Typed, so that those of
+        // the wrapped resource are used
+        let resource = block(include_str!("resource.rs"), "pub trait Resource ");
+        let base_logic = resource.find("    /// base logic").unwrap();
+        let handlers = resource.find("    // default handlers").unwrap();
+        let typed = fn_names(block(include_str!("typed.rs"),
+                                   "impl<R: TypedResource + Any> Resource for Typed<R>"));
+        let hooks = fn_names(&resource[..base_logic]).into_iter()
+            .chain(fn_names(&resource[handlers..]).into_iter())
+            .filter(|name| name != "resource_handle");
+        for name in hooks {
+            assert!(typed.contains(&name), "Typed does not implement Resource::{}", name);
+        }
+    }
+}